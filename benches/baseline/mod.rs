@@ -0,0 +1,56 @@
+use ahash::AHashSet;
+use robo::{
+    botris::types::{Command, Piece},
+    movegen::Placement,
+    tetris_core::{engine::BitBoard, piece::FallingPiece, ruleset::Ruleset},
+};
+
+/// The generator `move_gen` replaced, to measure against: every rotation from spawn, shifted and
+/// sonic dropped, then turned once more. It misses tucks and most spins.
+pub fn baseline_move_gen(board: &BitBoard, piece: Piece, rules: &Ruleset) -> AHashSet<Placement> {
+    use Command::*;
+    let rotation_sets = [vec![], vec![RotateCw], vec![RotateCcw], vec![RotateCcw, RotateCcw]];
+
+    let initial_falling_piece = FallingPiece::spawn(piece, 10, rules.visible_height);
+    if board.collides(&initial_falling_piece) {
+        return AHashSet::new();
+    }
+
+    let mut rotated_pieces = AHashSet::new();
+    for rotation_set in rotation_sets {
+        if let Some(rotated_piece) =
+            board.try_commands(&initial_falling_piece, &rotation_set, rules)
+        {
+            rotated_pieces.insert(rotated_piece);
+        }
+    }
+    let mut moved_and_soniced = AHashSet::new();
+    for piece in rotated_pieces {
+        for direction in [MoveLeft, MoveRight] {
+            let mut moving_piece = piece;
+            while let Some(moved_piece) = board.try_command(&moving_piece, direction, rules) {
+                moving_piece = moved_piece;
+                moved_and_soniced.insert(board.force_sonic_drop(&moving_piece));
+            }
+        }
+        moved_and_soniced.insert(board.force_sonic_drop(&piece));
+    }
+
+    let mut generated = AHashSet::new();
+    for piece in &moved_and_soniced {
+        generated.insert(Placement::new(board, piece, None, rules));
+    }
+    for spin in [RotateCcw, RotateCw] {
+        for piece in &moved_and_soniced {
+            if let Some(spun) = board.try_command(piece, spin, rules) {
+                generated.insert(Placement::new(
+                    board,
+                    &board.force_sonic_drop(&spun),
+                    None,
+                    rules,
+                ));
+            }
+        }
+    }
+    generated
+}
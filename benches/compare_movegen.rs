@@ -1,10 +1,13 @@
+use baseline::baseline_move_gen;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use robo::{
     botris::types::Piece,
-    movegen::{move_gen, move_gen_with_action},
-    tetris_core::engine::BitBoard,
+    movegen::{move_gen, move_gen_reference, move_gen_with_action},
+    tetris_core::{engine::BitBoard, ruleset::Ruleset},
 };
 
+mod baseline;
+
 fn bench_fibs(c: &mut Criterion) {
     let board = BitBoard::from_strs(&[
         "[][]                ",
@@ -20,10 +23,22 @@ fn bench_fibs(c: &mut Criterion) {
     let piece = Piece::T;
     let mut group = c.benchmark_group("movegens");
     group.bench_function("record_action", |b| {
-        b.iter(|| black_box(move_gen_with_action(black_box(&board), black_box(piece))))
+        b.iter(|| {
+            black_box(move_gen_with_action(black_box(&board), black_box(piece), &Ruleset::BOTRIS))
+        })
     });
     group.bench_function("no_action", |b| {
-        b.iter(|| black_box(move_gen(black_box(&board), black_box(piece))))
+        b.iter(|| black_box(move_gen(black_box(&board), black_box(piece), &Ruleset::BOTRIS)))
+    });
+    group.bench_function("baseline", |b| {
+        b.iter(|| {
+            black_box(baseline_move_gen(black_box(&board), black_box(piece), &Ruleset::BOTRIS))
+        })
+    });
+    group.bench_function("reference", |b| {
+        b.iter(|| {
+            black_box(move_gen_reference(black_box(&board), black_box(piece), &Ruleset::BOTRIS))
+        })
     });
 }
 
@@ -1,9 +1,16 @@
+use baseline::baseline_move_gen;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 // use pprof::criterion::{Output, PProfProfiler};
-use robo::{botris::types::Piece, movegen::move_gen, tetris_core::engine::BitBoard};
+use robo::{
+    botris::types::Piece,
+    movegen::{move_gen, move_gen_with_path},
+    tetris_core::{engine::BitBoard, ruleset::Ruleset},
+};
 
 // use std::time::Instant;
 
+mod baseline;
+
 fn benchmark_movegen(c: &mut Criterion) {
     let pieces = [Piece::I, Piece::O, Piece::T, Piece::L, Piece::J, Piece::S, Piece::Z];
 
@@ -62,7 +69,27 @@ fn benchmark_movegen(c: &mut Criterion) {
 
         for piece in pieces {
             group.bench_function(format!("{piece:?}"), |b| {
-                b.iter(|| black_box(move_gen(black_box(&board), black_box(piece))))
+                b.iter(|| {
+                    black_box(move_gen(black_box(&board), black_box(piece), &Ruleset::BOTRIS))
+                })
+            });
+            group.bench_function(format!("{piece:?} baseline"), |b| {
+                b.iter(|| {
+                    black_box(baseline_move_gen(
+                        black_box(&board),
+                        black_box(piece),
+                        &Ruleset::BOTRIS,
+                    ))
+                })
+            });
+            group.bench_function(format!("{piece:?} with path"), |b| {
+                b.iter(|| {
+                    black_box(move_gen_with_path(
+                        black_box(&board),
+                        black_box(piece),
+                        &Ruleset::BOTRIS,
+                    ))
+                })
             });
         }
 
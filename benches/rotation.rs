@@ -0,0 +1,216 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use robo::{
+    botris::types::Command,
+    tetris_core::{engine::BitBoard, piece::FallingPiece, rotation::PIECES, ruleset::Ruleset},
+};
+
+/// rotation before the tables were precomputed: shapes rebuilt and kicks copied on every call
+mod rebuilt {
+    use robo::{
+        botris::types::{Command, Piece},
+        tetris_core::{
+            engine::BitBoard, piece::FallingPiece, rotation::srs_shapes, ruleset::Ruleset,
+        },
+    };
+
+    pub fn try_rotate(
+        board: &BitBoard,
+        falling_piece: &FallingPiece,
+        command: Command,
+        rules: &Ruleset,
+    ) -> Option<FallingPiece> {
+        let mut tentative_piece = *falling_piece;
+        let new_rotation = (tentative_piece.rotation
+            + match command {
+                Command::RotateCcw => 3,
+                Command::RotateCw => 1,
+                _ => panic!(),
+            })
+            % 4;
+        let rotations = srs_shapes(tentative_piece.piece);
+        let old_rel_coords = rotations[tentative_piece.rotation as usize];
+        let new_rel_coords = rotations[new_rotation as usize];
+        for i in 0..4 {
+            tentative_piece.coords[i].0 += new_rel_coords[i].0 - old_rel_coords[i].0;
+            tentative_piece.coords[i].1 += new_rel_coords[i].1 - old_rel_coords[i].1;
+        }
+        tentative_piece.rotation = new_rotation;
+
+        for [x_kick, y_kick] in
+            kicks(tentative_piece.piece, command)[tentative_piece.rotation as usize]
+        {
+            let mut kicked_piece = tentative_piece;
+            kicked_piece.shift(y_kick, x_kick);
+            if !board.collides(&kicked_piece) {
+                return Some(board.apply_gravity(&kicked_piece, rules));
+            }
+        }
+        None
+    }
+
+    fn kicks(piece: Piece, command: Command) -> [[[i8; 2]; 5]; 4] {
+        match piece {
+            Piece::I => match command {
+                Command::RotateCcw => [
+                    [[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 1-0
+                    [[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 2-1
+                    [[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]], // 3-2
+                    [[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 0-3
+                ],
+                Command::RotateCw => [
+                    [[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 3-0
+                    [[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]], // 0-1
+                    [[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 1-2
+                    [[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 2-3
+                ],
+                _ => panic!(),
+            },
+            Piece::O => match command {
+                Command::RotateCcw => [
+                    [[-1, 1], [-2, 1], [-1, 2], [0, 1], [0, 0]],     // 1-0
+                    [[1, 1], [2, 1], [1, 2], [0, 1], [0, 0]],        // 2-1
+                    [[1, -1], [2, -1], [1, -2], [0, -1], [0, 0]],    // 3-2
+                    [[-1, -1], [-2, -1], [-1, -2], [0, -1], [0, 0]], // 0-3
+                ],
+                Command::RotateCw => [
+                    [[1, 1], [2, 1], [1, 2], [0, 1], [0, 0]],        // 3-0
+                    [[1, -1], [2, -1], [1, -2], [0, -1], [0, 0]],    // 0-1
+                    [[-1, -1], [-2, -1], [-1, -2], [0, -1], [0, 0]], // 1-2
+                    [[-1, 1], [-2, 1], [-1, 2], [0, 1], [0, 0]],     // 2-3
+                ],
+                _ => panic!(),
+            },
+            _ => match command {
+                Command::RotateCcw => [
+                    [[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],     // 1-0
+                    [[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]], // 2-1
+                    [[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],  // 3-2
+                    [[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],    // 0-3
+                ],
+                Command::RotateCw => [
+                    [[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],  // 3-0
+                    [[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]], // 0-1
+                    [[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],     // 1-2
+                    [[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],    // 2-3
+                ],
+                _ => panic!(),
+            },
+        }
+    }
+}
+
+/// every piece in every rotation at every spot it fits in the bottom `rows` of the board
+fn free_pieces(board: &BitBoard, rules: &Ruleset, rows: i8) -> Vec<FallingPiece> {
+    let mut pieces = vec![];
+    for piece in PIECES {
+        let mut falling_piece = FallingPiece::new(piece);
+        for _ in 0..4 {
+            let lowest = falling_piece.coords.iter().map(|&(y, _)| y).min().unwrap();
+            let leftmost = falling_piece.coords.iter().map(|&(_, x)| x).min().unwrap();
+            for y in 0..rows {
+                for x in 0..10 {
+                    let mut placed = falling_piece;
+                    placed.shift(y - lowest, x - leftmost);
+                    if !board.collides(&placed) {
+                        pieces.push(placed);
+                    }
+                }
+            }
+            falling_piece.rotate(Command::RotateCw, rules.rotation_system);
+        }
+    }
+    pieces
+}
+
+fn bench_board(c: &mut Criterion, name: &str, board: &BitBoard, pieces: &[FallingPiece]) {
+    let rules = Ruleset::BOTRIS;
+    let mut group = c.benchmark_group(format!("rotation: {name}"));
+    group.bench_function("precomputed", |b| {
+        b.iter(|| {
+            for falling_piece in pieces {
+                for command in [Command::RotateCw, Command::RotateCcw] {
+                    black_box(board.try_command(black_box(falling_piece), command, &rules));
+                }
+            }
+        })
+    });
+    group.bench_function("rebuilt", |b| {
+        b.iter(|| {
+            for falling_piece in pieces {
+                for command in [Command::RotateCw, Command::RotateCcw] {
+                    black_box(rebuilt::try_rotate(
+                        board,
+                        black_box(falling_piece),
+                        command,
+                        &rules,
+                    ));
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_rotation(c: &mut Criterion) {
+    let rules = Ruleset::BOTRIS;
+
+    let open = BitBoard::from_strs(&[
+        "                    ",
+        "                  []",
+        "[][]            [][]",
+        "[][][]        [][][]",
+        "[][][]      [][][][]",
+        "[][][][]    [][][][]",
+    ]);
+    // every piece in every rotation, low enough that some rotations kick
+    let mut pieces = vec![];
+    for piece in PIECES {
+        let mut falling_piece = FallingPiece::new(piece);
+        falling_piece.shift(-14, 0);
+        for _ in 0..4 {
+            pieces.push(falling_piece);
+            falling_piece.rotate(Command::RotateCw, rules.rotation_system);
+        }
+    }
+    bench_board(c, "open", &open, &pieces);
+
+    // crowded boards, with pieces packed into every gap, where most turns run through several kicks
+    let crowded = [
+        (
+            "tspin",
+            BitBoard::from_strs(&[
+                "                  []",
+                "                  []",
+                "[][]            [][]",
+                "[][][]        [][][]",
+                "[][][]      [][][][]",
+                "[][][][]    [][][][]",
+                "[][][][]      [][][]",
+                "[][][][][]  [][][][]",
+            ]),
+        ),
+        (
+            "terrible",
+            BitBoard::from_strs(&[
+                "    [][][][][][][][]",
+                "    [][][][][][][][]",
+                "                  []",
+                "                  []",
+                "[][][][][][][]    []",
+                "[][][][][][][]    []",
+                "[]                []",
+                "[]                []",
+                "[]  [][][][][][][][]",
+                "[]  [][][][][][][][]",
+                "[]                  ",
+                "[]                  ",
+            ]),
+        ),
+    ];
+    for (name, board) in crowded {
+        bench_board(c, name, &board, &free_pieces(&board, &rules, 12));
+    }
+}
+
+criterion_group!(benches, bench_rotation);
+criterion_main!(benches);
@@ -4,7 +4,12 @@ pub mod websocket;
 use api_messages::BotrisMsg;
 use dotenv::{dotenv, var};
 use owo_colors::OwoColorize;
-use robo::{akirobo::Akirobo, botris::types::Command, tetris_core::snapshot::GameSnapshot};
+use robo::{
+    akirobo::Akirobo,
+    botris::types::Command,
+    search::TimeBudget,
+    tetris_core::{ruleset::Ruleset, snapshot::GameSnapshot},
+};
 use websocket::BotrisWebSocket;
 
 // #[tokio::main]
@@ -20,21 +25,38 @@ fn main() {
     let token = var("TOKEN").expect("Set TOKEN in .env");
     let room_key = var("ROOMKEY").expect("Set ROOMKEY in .env");
     let url = format!("wss://botrisbattle.com/ws?token={token}&roomKey={room_key}");
+    // optional, the pps to think at. without it every move searches the full lookahead.
+    let budget = match var("PPS") {
+        Ok(pps) => TimeBudget::Pps(pps.parse().expect("PPS should be a number")),
+        Err(_) => TimeBudget::Unlimited,
+    };
 
     let mut ws = BotrisWebSocket::new(url);
+    // kept between moves so each search can pick up where the last one left off
+    let mut akirobo = Akirobo::new();
+    akirobo.budget = budget;
+    // optional, the number of threads to search on
+    if let Ok(threads) = var("THREADS") {
+        akirobo.set_threads(threads.parse().expect("THREADS should be a number"));
+    }
+    // optional, how many guesses at the unseen queue to average over. 1 plans around a single guess.
+    if let Ok(samples) = var("SAMPLES") {
+        akirobo.samples = samples.parse().expect("SAMPLES should be a number");
+    }
 
     loop {
         if let Some(message) = ws.read() {
             use BotrisMsg::*;
             match message {
                 RequestMove { game_state, .. } => {
-                    let mut akirobo = Akirobo::new();
                     if game_state.held.is_none() {
                         println!("Holding first piece!");
                         ws.send_actions(vec![Command::Hold])
                     } else {
-                        let commands =
-                            akirobo.suggest_action(&GameSnapshot::from_state(&game_state));
+                        let commands = akirobo.suggest_action(
+                            &GameSnapshot::from_state(&game_state),
+                            &Ruleset::BOTRIS,
+                        );
                         ws.send_actions(commands);
                     }
                 }
@@ -1,145 +1,326 @@
 use std::{
-    array,
-    collections::BTreeSet,
-    rc::Rc,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap},
+    ops::RangeInclusive,
+    sync::Arc,
     time::Instant,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
+use ordered_float::OrderedFloat;
 use owo_colors::OwoColorize;
+use rand::{rngs::StdRng, SeedableRng};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 
 use crate::{
-    botris::types::Command,
+    botris::types::{Command, Piece},
     evaluation::{default_eval::DefaultEval, Evaluate},
-    movegen::{move_gen, move_gen_with_action},
+    movegen::{move_gen, move_gen_snapshot, Move, Placement},
+    search::{Search, SearchStats, TimeBudget, DEATH_WIGGLE},
     searchtree::{print_nodes, EvaledPlacementNode},
-    tetris_core::{engine::BoardData, snapshot::GameSnapshot},
+    tetris_core::{
+        engine::{BitBoard, BoardData},
+        ruleset::Ruleset,
+        snapshot::GameSnapshot,
+    },
 };
 
-const LOOKAHEAD_DEPTH: usize = 12; // # pieces in queue being considered (0 = only current, disables rest)
+const LOOKAHEAD_DEPTH: usize = 12; // default `Akirobo::lookahead`
 const DEPTH_ZERO_SIZE: usize = usize::MAX; // maybe small number kinda makes bot play safer?
 
-const BRANCHING_FACTOR: usize = 15; // if nonzero, use a large search width
+const BRANCHING_FACTOR: usize = 15; // default `Akirobo::branching`
 const MAX_SEARCH_WIDTH: usize = usize::MAX;
+const SAMPLES: usize = 4; // default `Akirobo::samples`
 
-// expect ~ pow(BRANCHING_FACTOR, LOOKAHEAD) leaves at final depth, or MAX_SEARCH_WIDTH.
+// expect ~ pow(branching, lookahead) leaves at final depth, or MAX_SEARCH_WIDTH.
 
-#[derive(Default)]
-pub struct Akirobo {}
+pub struct Akirobo<E: Evaluate = DefaultEval> {
+    evaluator: E,
+    /// print the suggestion, its vision and timings for every move
+    pub verbose: bool,
+    /// pieces in the queue searched past the current one. 0 only places the current piece.
+    pub lookahead: usize,
+    /// children kept per expanded node, best first. 0 keeps them all.
+    pub branching: usize,
+    pub budget: TimeBudget,
+    /// how many guesses at the pieces past the visible queue to average over.
+    /// 1 plans around a single guess.
+    pub samples: usize,
+    /// seeds the guesses
+    pub seed: u64,
+    /// stats of the last `suggest_action`
+    pub last_search: SearchStats,
+    tree: Option<SearchTree>,
+    /// None searches on the calling thread
+    pool: Option<ThreadPool>,
+}
+
+/// What the last search expanded, kept so the next one can pick up under the move that was played.
+struct SearchTree {
+    /// the root of the suggested action
+    played: Arc<EvaledPlacementNode>,
+    queue: Vec<Piece>,
+    /// the children kept for every node that was fully expanded, by the node's id
+    expanded: AHashMap<usize, Vec<Arc<EvaledPlacementNode>>>,
+}
+
+impl SearchTree {
+    /// How many pieces of `queue` the old search had in the same order after the played piece,
+    /// or None if `genesis` isn't the state the played move was expected to lead to.
+    /// Assumes the rules haven't changed.
+    fn follows(&self, genesis: &GameSnapshot, queue: &[Piece]) -> Option<usize> {
+        let data = &self.played.board_data;
+        let predicted = genesis.matrix == self.played.board_after_clears
+            && genesis.held == self.played.held
+            && self.queue.first() == Some(&genesis.falling_piece.piece)
+            && genesis.b2b == data.b2b
+            && genesis.combo == data.combo
+            // new garbage invalidates every board below
+            && genesis.incoming_garbage == data.incoming
+            && genesis.garbage_holes == data.garbage_holes;
+        predicted
+            .then(|| self.queue[1..].iter().zip(queue).take_while(|(old, new)| old == new).count())
+    }
+}
+
+/// What decides how a node plays out, leaving out how it got there.
+/// Two nodes of the same depth with the same key only differ in the attack sent on the way,
+/// so only the better scored one is worth expanding.
+#[derive(PartialEq, Eq, Hash)]
+struct StateKey {
+    board: BitBoard,
+    held: Piece,
+    data: BoardData,
+}
+
+impl StateKey {
+    fn of(node: &EvaledPlacementNode) -> Self {
+        StateKey {
+            board: node.board_after_clears,
+            held: node.held,
+            data: BoardData { cummulative_attack: 0, simulated_garbage: 0, ..node.board_data },
+        }
+    }
+}
 
 impl Akirobo {
     pub fn new() -> Self {
-        Akirobo {}
+        Akirobo::with_evaluator(DefaultEval {})
     }
+}
+
+impl Default for Akirobo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    pub fn suggest_action(&mut self, genesis: &GameSnapshot) -> Vec<Command> {
+impl<E: Evaluate> Akirobo<E> {
+    pub fn with_evaluator(evaluator: E) -> Self {
+        Akirobo {
+            evaluator,
+            verbose: true,
+            lookahead: LOOKAHEAD_DEPTH,
+            branching: BRANCHING_FACTOR,
+            budget: TimeBudget::default(),
+            samples: SAMPLES,
+            seed: 0,
+            last_search: SearchStats::default(),
+            tree: None,
+            pool: None,
+        }
+    }
+
+    /// Expands each depth on `threads` threads. 1 searches on the calling thread.
+    /// Without a deadline, the suggestion doesn't depend on the number of threads.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.pool = match threads {
+            1 => None,
+            n => Some(
+                ThreadPoolBuilder::new()
+                    .num_threads(n)
+                    .build()
+                    .expect("couldn't start search threads"),
+            ),
+        };
+    }
+
+    /// the best action found within `self.budget`
+    pub fn suggest_action(&mut self, genesis: &GameSnapshot, rules: &Ruleset) -> Vec<Command> {
+        let deadline = self.budget.deadline(Instant::now());
+        self.suggest_action_by(genesis, rules, deadline)
+    }
+
+    /// Searches one depth at a time until `self.lookahead` or `deadline`, whichever comes first,
+    /// and suggests the action towards the best node of the deepest depth reached.
+    /// Parents are expanded best first, so a depth cut short by the deadline still has the most promising nodes.
+    /// Nodes of a depth that reach the same state by different paths are merged into the best scored one.
+    ///
+    /// Pieces past `genesis.queue` are guessed from what's left of the bag. With `self.samples` guesses,
+    /// the depths past the queue are searched once per guess and the move with the best average wins,
+    /// so one made up queue doesn't decide the move.
+    ///
+    /// If `genesis` is where the last suggestion was expected to lead, the subtree under it is carried over
+    /// as far as the queue still matches, and only the rest is searched.
+    pub fn suggest_action_by(
+        &mut self,
+        genesis: &GameSnapshot,
+        rules: &Ruleset,
+        deadline: Option<Instant>,
+    ) -> Vec<Command> {
         let start_time = Instant::now();
-        let evaluator = DefaultEval {};
+        let evaluator = &self.evaluator;
+        let lookahead = self.lookahead;
+
+        // seeded the same every time, so the same state always gets the same suggestion
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        // a queue that reaches the lookahead leaves nothing to guess
+        let samples = match genesis.queue.len() > lookahead {
+            true => 1,
+            false => self.samples.max(1),
+        };
+        let queues: Vec<Vec<Piece>> =
+            (0..samples).map(|_| genesis.sample_queue(&mut rng, lookahead + 1)).collect();
+        // depth `d` places `queue[d - 1]` and spawns `queue[d]`, so these depths are the same for every guess
+        let shared = match queues.len() {
+            1 => lookahead,
+            _ => genesis.queue.len().saturating_sub(1).min(lookahead),
+        };
+
+        let tree = self.tree.take();
+        let matching = tree.as_ref().and_then(|tree| tree.follows(genesis, &queues[0]));
+        let mut search =
+            SearchRun::new(deadline, tree.filter(|_| matching.is_some()), matching.unwrap_or(0));
 
         let genesis_board = genesis.matrix;
         let genesis_data = BoardData {
             b2b: genesis.b2b,
+            // the server doesn't say, but the last search knows
+            b2b_chain: search.tree.as_ref().map_or(0, |tree| tree.played.board_data.b2b_chain),
             combo: genesis.combo,
             cummulative_attack: 0,
             incoming: genesis.incoming_garbage,
             simulated_garbage: 0,
+            garbage_holes: genesis.garbage_holes,
         };
-        let first_piece = genesis.falling_piece.piece;
 
-        let mut tree_nodes: [BTreeSet<Rc<EvaledPlacementNode>>; LOOKAHEAD_DEPTH + 1] =
-            array::from_fn(|_| BTreeSet::new());
+        let mut tree_nodes: Vec<BTreeSet<Arc<EvaledPlacementNode>>> =
+            vec![BTreeSet::new(); lookahead + 1];
 
+        // root nodes are told apart by what they place and what they leave in hold
         let mut action_lookup = AHashMap::new();
-        for (placement, action) in move_gen_with_action(&genesis_board, first_piece) {
-            tree_nodes[0].insert(EvaledPlacementNode::new(
-                &genesis_board,
-                placement,
-                genesis.held,
-                None,
-                Some(genesis_data),
-                &evaluator,
-            ));
-            action_lookup.insert(placement, action);
-        }
-        // TODO: definitely need some refactoring...
-        for (placement, mut action) in move_gen_with_action(&genesis_board, genesis.held) {
-            tree_nodes[0].insert(EvaledPlacementNode::new(
+        let mut moves = move_gen_snapshot(genesis, rules);
+        // ties in score keep whichever node came first, so the order has to be the same every time
+        moves.sort_unstable_by_key(|candidate| (candidate.placement, candidate.held));
+        for Move { placement, held, action, .. } in moves {
+            if let Ok(node) = EvaledPlacementNode::new(
                 &genesis_board,
                 placement,
-                first_piece,
+                held,
                 None,
                 Some(genesis_data),
-                &evaluator,
-            ));
-            action.insert(0, Command::Hold);
-            action_lookup.insert(placement, action);
+                evaluator,
+                rules,
+                queues[0].first().copied(),
+            ) {
+                search.nodes += 1;
+                // the last search may have run out of time before expanding the played move
+                let origin = search.tree.as_ref().and_then(|tree| {
+                    tree.expanded
+                        .get(&tree.played.id())?
+                        .iter()
+                        .find(|old| old.placement == node.placement && old.held == node.held)
+                });
+                // a node that ties another's score isn't kept, so it doesn't need its origin either
+                if let (true, Some(origin)) = (tree_nodes[0].insert(node.clone()), origin) {
+                    search.origins.insert(node.id(), origin.clone());
+                }
+            }
+            action_lookup.insert((placement, held), action);
         }
 
-        // for each node in previous depth, add BRANCHING_FACTOR new nodes.
-        for depth in 1..=LOOKAHEAD_DEPTH {
-            let (before, after) = tree_nodes.split_at_mut(depth);
-            let prev_depth_nodes = &before[depth - 1];
-            let curr_depth_nodes = &mut after[0]; // starts empty
-            let filtered = match depth {
-                1 => prev_depth_nodes.iter().rev().take(DEPTH_ZERO_SIZE),
-                _ => prev_depth_nodes.iter().rev().take(MAX_SEARCH_WIDTH),
-            };
-            for node in filtered {
-                let mut children = BTreeSet::new();
-                for placement in move_gen(&node.board_after_clears, genesis.queue[depth - 1]) {
-                    children.insert(EvaledPlacementNode::new(
-                        &node.board_after_clears,
-                        placement,
-                        node.held,
-                        Some(node.clone()),
-                        None,
-                        &evaluator,
-                    ));
-                }
-                for placement in move_gen(&node.board_after_clears, node.held) {
-                    children.insert(EvaledPlacementNode::new(
-                        &node.board_after_clears,
-                        placement,
-                        genesis.queue[depth - 1],
-                        Some(node.clone()),
-                        None,
-                        &evaluator,
-                    ));
-                }
-                match BRANCHING_FACTOR {
-                    0 => curr_depth_nodes.append(&mut children),
-                    n => curr_depth_nodes.extend(children.into_iter().rev().take(n)),
+        let mut timed_out =
+            !self.expand_depths(&mut tree_nodes, 1..=lookahead, &queues[0], rules, &mut search);
+        let depth = tree_nodes.iter().rposition(|level| !level.is_empty()).unwrap_or(0);
+        let mut nodes = search.nodes;
+        let mut transpositions = search.transpositions;
+
+        // the other guesses only need the depths past the shared ones searched again
+        let mut samples = vec![];
+        if depth == lookahead && !timed_out {
+            samples.push(best_by_root(&tree_nodes[lookahead]));
+            for queue in &queues[1..] {
+                let mut sample_nodes: Vec<_> = (0..=lookahead)
+                    .map(|depth| match depth <= shared {
+                        true => tree_nodes[depth].clone(),
+                        false => BTreeSet::new(),
+                    })
+                    .collect();
+                let mut sample_search = SearchRun::new(deadline, None, 0);
+                let finished = self.expand_depths(
+                    &mut sample_nodes,
+                    shared + 1..=lookahead,
+                    queue,
+                    rules,
+                    &mut sample_search,
+                );
+                nodes += sample_search.nodes;
+                transpositions += sample_search.transpositions;
+                if !finished {
+                    timed_out = true;
+                    break;
                 }
+                samples.push(best_by_root(&sample_nodes[lookahead]));
             }
         }
 
-        let millis = start_time.elapsed().as_millis();
-        let last_depth_frames = tree_nodes.last().unwrap().len();
-
-        if last_depth_frames == 0 {
-            genesis_board.print_board(None);
-            println!("Doom imminent? :)");
-            for level in tree_nodes.iter_mut().rev() {
-                if !level.is_empty() {
-                    return action_lookup
-                        .get(&level.pop_last().unwrap().get_root_placement())
-                        .unwrap()
-                        .to_owned();
-                }
+        let elapsed = start_time.elapsed();
+        let millis = elapsed.as_millis();
+        let SearchRun { tree, expanded, reused, .. } = search;
+        drop(tree);
+        self.last_search = SearchStats {
+            depth,
+            nodes,
+            reused,
+            transpositions,
+            samples: samples.len(),
+            iterations: 0,
+            elapsed,
+            timed_out,
+        };
+        let last_depth_frames = tree_nodes[depth].len();
+        let best_node = match samples.is_empty() {
+            true => tree_nodes[depth].last().cloned(),
+            false => best_on_average(&samples),
+        };
+        if let Some(best_node) = &best_node {
+            self.tree = Some(SearchTree {
+                played: best_node.get_root(),
+                queue: queues[0].clone(),
+                expanded,
+            });
+        }
+
+        // with nothing to place, even a search that wasn't meant to look ahead is lost
+        if (depth < lookahead && !timed_out) || best_node.is_none() {
+            if self.verbose {
+                genesis_board.print_board(None);
+                println!("Doom imminent? :)");
             }
-            // death wiggle
-            return vec![
-                Command::SonicLeft,
-                Command::SonicRight,
-                Command::SonicLeft,
-                Command::SonicRight,
-            ];
+            if let Some(node) = best_node {
+                let root = node.get_root();
+                return action_lookup[&(root.placement, root.held)].to_owned();
+            }
+            return DEATH_WIGGLE.to_vec();
         }
 
-        let last_nodes = tree_nodes.last_mut().unwrap();
-        let best_node = last_nodes.pop_last().unwrap();
+        let best_node = best_node.unwrap();
         let best_node_root = best_node.get_root();
-        let suggestion = action_lookup.get(&best_node_root.placement).unwrap().to_owned();
+        let suggestion = action_lookup[&(best_node_root.placement, best_node_root.held)].to_owned();
+
+        if !self.verbose {
+            return suggestion;
+        }
 
         // println!("Showing: all first moves");
         // print_nodes(tree_nodes[0].iter().rev().collect::<Vec<_>>(), 5);
@@ -153,8 +334,14 @@ impl Akirobo {
         println!("Suggestion: {:?}", suggestion);
         evaluator.eval(&best_node_root.board_after_clears, &best_node_root.board_data, true);
         println!(
-            "{} placements at final depth in {}ms ({:.2}pps)",
+            "{} placements at depth {}{}, {} nodes ({} reused, {} transpositions) over {} queues in {}ms ({:.2}pps)",
             last_depth_frames,
+            depth,
+            if timed_out { " (out of time)" } else { "" },
+            nodes,
+            reused,
+            transpositions,
+            self.last_search.samples,
             millis.blue(),
             1000.0 / millis as f32,
         );
@@ -162,4 +349,455 @@ impl Akirobo {
 
         suggestion
     }
+
+    /// Fills `levels[depth]` from the level above for every depth in `depths`, placing `queue[depth - 1]`.
+    /// Returns false if the deadline passed first.
+    fn expand_depths(
+        &self,
+        levels: &mut [BTreeSet<Arc<EvaledPlacementNode>>],
+        depths: RangeInclusive<usize>,
+        queue: &[Piece],
+        rules: &Ruleset,
+        search: &mut SearchRun,
+    ) -> bool {
+        let evaluator = &self.evaluator;
+        // for each node in previous depth, add `self.branching` new nodes.
+        for depth in depths {
+            let (before, after) = levels.split_at_mut(depth);
+            let prev_depth_nodes = &before[depth - 1];
+            let curr_depth_nodes = &mut after[0]; // starts empty
+            let filtered = match depth {
+                1 => prev_depth_nodes.iter().rev().take(DEPTH_ZERO_SIZE),
+                _ => prev_depth_nodes.iter().rev().take(MAX_SEARCH_WIDTH),
+            };
+            let parents: Vec<_> = filtered.filter(|node| node.clear.top_out.is_none()).collect();
+            let expand_parent = |node: &&Arc<EvaledPlacementNode>| {
+                if search.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return None;
+                }
+                // old children were placed with the same pieces as long as the queue matches
+                let old_children = search
+                    .origins
+                    .get(&node.id())
+                    .filter(|_| depth < search.matching)
+                    .and_then(|origin| search.tree.as_ref()?.expanded.get(&origin.id()));
+                Some(match (old_children, &search.tree) {
+                    (Some(old_children), Some(tree)) => {
+                        reuse(node, old_children, &tree.played.board_data, evaluator)
+                    }
+                    _ => {
+                        let (piece, next_piece) = (queue[depth - 1], queue.get(depth).copied());
+                        expand(node, piece, next_piece, evaluator, rules)
+                    }
+                })
+            };
+            // expanded in parallel, but collected in order so the beam is the same as searching serially
+            let expansions: Vec<_> = match &self.pool {
+                Some(pool) => pool.install(|| parents.par_iter().map(expand_parent).collect()),
+                None => parents.iter().map(expand_parent).collect(),
+            };
+            // the parents expanded before the deadline still make the level
+            let finished = expansions.iter().all(Option::is_some);
+            let expansions: Vec<_> =
+                expansions.into_iter().map_while(|expansion| expansion).collect();
+            // every node of this depth has placed the same pieces, so the same state plays out the same from here.
+            // children are taken best first over all parents, so each state goes to its best node that makes a cut,
+            // and the `branching` children a parent keeps are states no sibling took
+            let mut kept = vec![vec![]; expansions.len()];
+            let mut taken = AHashSet::new();
+            // the best child not yet taken of each parent that isn't full. ties go to the earlier parent
+            let mut next: BinaryHeap<_> = expansions
+                .iter()
+                .enumerate()
+                .filter_map(|(parent, expansion)| {
+                    Some((expansion.children.first()?.0.score, Reverse(parent), 0))
+                })
+                .collect();
+            while let Some((_, Reverse(parent), index)) = next.pop() {
+                let children = &expansions[parent].children;
+                let (child, origin) = &children[index];
+                if taken.insert(StateKey::of(child)) {
+                    // a node that ties another's score isn't kept, but still takes its place in the cut
+                    if curr_depth_nodes.insert(child.clone()) {
+                        if let Some(origin) = origin {
+                            search.origins.insert(child.id(), origin.clone());
+                            search.reused += 1;
+                        }
+                    }
+                    kept[parent].push(child.clone());
+                } else {
+                    search.transpositions += 1;
+                }
+                let full = self.branching != 0 && kept[parent].len() == self.branching;
+                if let Some((child, _)) = children.get(index + 1).filter(|_| !full) {
+                    next.push((child.score, Reverse(parent), index + 1));
+                }
+            }
+            for ((node, expansion), kept) in parents.iter().zip(&expansions).zip(kept) {
+                search.nodes += expansion.evaluated;
+                search.expanded.insert(node.id(), kept);
+            }
+            if !finished {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<E: Evaluate> Search for Akirobo<E> {
+    fn suggest_action(&mut self, genesis: &GameSnapshot, rules: &Ruleset) -> Vec<Command> {
+        Akirobo::suggest_action(self, genesis, rules)
+    }
+
+    fn last_search(&self) -> SearchStats {
+        self.last_search
+    }
+}
+
+/// Bookkeeping for the depths of one search.
+struct SearchRun {
+    deadline: Option<Instant>,
+    /// the last search, if this one picks up under its played move
+    tree: Option<SearchTree>,
+    /// nodes above this depth can be copied from `tree`
+    matching: usize,
+    /// the node of `tree` each new node was copied from, by the new node's id
+    origins: AHashMap<usize, Arc<EvaledPlacementNode>>,
+    /// the children kept for every node that was fully expanded, by the node's id
+    expanded: AHashMap<usize, Vec<Arc<EvaledPlacementNode>>>,
+    nodes: usize,
+    reused: usize,
+    transpositions: usize,
+}
+
+impl SearchRun {
+    fn new(deadline: Option<Instant>, tree: Option<SearchTree>, matching: usize) -> Self {
+        SearchRun {
+            deadline,
+            tree,
+            matching,
+            origins: AHashMap::new(),
+            expanded: AHashMap::new(),
+            nodes: 0,
+            reused: 0,
+            transpositions: 0,
+        }
+    }
+}
+
+/// the best leaf under each root, by what the root places and leaves in hold
+fn best_by_root(
+    leaves: &BTreeSet<Arc<EvaledPlacementNode>>,
+) -> BTreeMap<(Placement, Piece), Arc<EvaledPlacementNode>> {
+    let mut best = BTreeMap::new();
+    // worst first, so better leaves overwrite
+    for leaf in leaves {
+        let root = leaf.get_root();
+        best.insert((root.placement, root.held), leaf.clone());
+    }
+    best
+}
+
+/// The leaf of the first sample under the root with the best average over all samples.
+/// A root the beam dropped in a sample scores as that sample's worst leaf.
+fn best_on_average(
+    samples: &[BTreeMap<(Placement, Piece), Arc<EvaledPlacementNode>>],
+) -> Option<Arc<EvaledPlacementNode>> {
+    let worst: Vec<f32> = samples
+        .iter()
+        .map(|sample| {
+            sample.values().map(|leaf| leaf.score).min().map_or(f32::NEG_INFINITY, |score| score.0)
+        })
+        .collect();
+    samples[0]
+        .iter()
+        .max_by_key(|(root, _)| {
+            OrderedFloat(
+                samples
+                    .iter()
+                    .zip(&worst)
+                    .map(|(sample, &worst)| sample.get(root).map_or(worst, |leaf| leaf.score.0))
+                    .sum::<f32>(),
+            )
+        })
+        .map(|(_, leaf)| leaf.clone())
+}
+
+/// The children of one node, best first. The beam keeps `branching` of them.
+struct Expansion {
+    /// each with the node of the last search it was copied from, if it was
+    children: Vec<(Arc<EvaledPlacementNode>, Option<Arc<EvaledPlacementNode>>)>,
+    /// nodes that were evaluated from scratch
+    evaluated: usize,
+}
+
+/// places `piece`, or the held piece and keeps `piece`, on the board of `node`
+fn expand(
+    node: &Arc<EvaledPlacementNode>,
+    piece: Piece,
+    next_piece: Option<Piece>,
+    evaluator: &impl Evaluate,
+    rules: &Ruleset,
+) -> Expansion {
+    let mut placements: Vec<_> = move_gen(&node.board_after_clears, piece, rules)
+        .into_iter()
+        .map(|placement| (placement, node.held))
+        .collect();
+    if node.held != piece {
+        placements.extend(
+            move_gen(&node.board_after_clears, node.held, rules)
+                .into_iter()
+                .map(|placement| (placement, piece)),
+        );
+    }
+    // ties in score keep whichever node came first, so the order has to be the same every time
+    placements.sort_unstable();
+    let mut children = BTreeSet::new();
+    let mut evaluated = 0;
+    for (placement, held) in placements {
+        if let Ok(child) = EvaledPlacementNode::new(
+            &node.board_after_clears,
+            placement,
+            held,
+            Some(node.clone()),
+            None,
+            evaluator,
+            rules,
+            next_piece,
+        ) {
+            evaluated += 1;
+            children.insert(child);
+        }
+    }
+    let children = children.into_iter().rev().map(|child| (child, None)).collect();
+    Expansion { children, evaluated }
+}
+
+/// the children the last search kept for the node `node` was copied from, copied under `node`
+fn reuse(
+    node: &Arc<EvaledPlacementNode>,
+    old_children: &[Arc<EvaledPlacementNode>],
+    base: &BoardData,
+    evaluator: &impl Evaluate,
+) -> Expansion {
+    let mut children: Vec<_> = old_children
+        .iter()
+        .map(|old| (old.rebased(node.clone(), base, evaluator), Some(old.clone())))
+        .collect();
+    // rebasing can reorder them
+    children.sort_by_key(|(child, _)| Reverse(child.score));
+    Expansion { children, evaluated: 0 }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::BTreeSet,
+        time::{Duration, Instant},
+    };
+
+    use ahash::AHashSet;
+
+    use super::{Akirobo, SearchRun, StateKey};
+    use crate::{
+        botris::types::{Command, Piece},
+        evaluation::{default_eval::DefaultEval, NoEval},
+        movegen::{move_gen, move_gen_snapshot},
+        search::TimeBudget,
+        searchtree::EvaledPlacementNode,
+        tetris_core::{
+            engine::{BitBoard, BoardData, EMPTY_BOARD},
+            game::Game,
+            piece::FallingPiece,
+            ruleset::Ruleset,
+            snapshot::GameSnapshot,
+        },
+        validation::replay_action,
+    };
+
+    /// deep enough to guess past the visible queue, shallow enough to be quick
+    fn shallow() -> Akirobo {
+        let mut bot = Akirobo::new();
+        bot.verbose = false;
+        bot.lookahead = 7;
+        bot.branching = 6;
+        bot
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let rules = Ruleset::BOTRIS;
+        let snapshot = GameSnapshot {
+            falling_piece: FallingPiece::new(Piece::T),
+            held: Piece::I,
+            can_hold: true,
+            queue: [Piece::J, Piece::L, Piece::O, Piece::S, Piece::Z].repeat(3),
+            ..Default::default()
+        };
+        let mut bot = Akirobo::with_evaluator(NoEval {});
+        bot.verbose = false;
+
+        let action = bot.suggest_action(&snapshot, &rules);
+        assert_eq!(bot.last_search.depth, bot.lookahead);
+        assert!(!bot.last_search.timed_out);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+        // the whole lookahead is in the queue, so there's only the one queue
+        assert_eq!(bot.last_search.samples, 1);
+
+        // out of time before the first piece of the queue, but there's still a move
+        let action = bot.suggest_action_by(&snapshot, &rules, Some(Instant::now()));
+        assert_eq!(bot.last_search.depth, 0);
+        assert!(bot.last_search.timed_out);
+        assert!(bot.last_search.nodes > 0);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+
+        let start = Instant::now();
+        assert_eq!(TimeBudget::Unlimited.deadline(start), None);
+        assert_eq!(TimeBudget::Pps(4.0).deadline(start), Some(start + Duration::from_millis(250)));
+        assert_eq!(TimeBudget::Pps(0.0).deadline(start), None);
+    }
+
+    #[test]
+    fn test_topped_out() {
+        use Command::*;
+        let rules = Ruleset::BOTRIS;
+        let snapshot = GameSnapshot {
+            matrix: BitBoard::from_strs(&["    [][][][][][][][]"; 21]),
+            falling_piece: FallingPiece::new(Piece::T),
+            held: Piece::I,
+            can_hold: true,
+            queue: vec![Piece::O],
+            ..Default::default()
+        };
+        assert!(move_gen_snapshot(&snapshot, &rules).is_empty());
+        // no root moves at all, even when there's nothing to look ahead at
+        for lookahead in [0, 1] {
+            let mut bot = shallow();
+            bot.lookahead = lookahead;
+            let action = bot.suggest_action(&snapshot, &rules);
+            assert_eq!(action, [SonicLeft, SonicRight, SonicLeft, SonicRight]);
+            assert_eq!(bot.last_search.depth, 0);
+        }
+    }
+
+    #[test]
+    fn test_reuse_tree() {
+        let rules = Ruleset::BOTRIS;
+        let mut game = Game::new(3, rules);
+        game.apply(&[Command::Hold]);
+        let mut bot = shallow();
+        let snapshot = game.snapshot().unwrap();
+        let action = bot.suggest_action(&snapshot, &rules);
+        assert_eq!(bot.last_search.reused, 0);
+        game.apply(&action);
+
+        // the visible queue is the same, so everything under the played move down to it is kept
+        let snapshot = game.snapshot().unwrap();
+        let action = bot.suggest_action(&snapshot, &rules);
+        assert!(bot.last_search.reused > 0);
+        assert_eq!(bot.last_search.depth, bot.lookahead);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+
+        // out of time before the played move was expanded, so there's nothing under it to reuse
+        game.apply(&action);
+        let snapshot = game.snapshot().unwrap();
+        let action = bot.suggest_action_by(&snapshot, &rules, Some(Instant::now()));
+        assert_eq!(bot.last_search.depth, 0);
+        game.apply(&action);
+        let snapshot = game.snapshot().unwrap();
+        let action = bot.suggest_action(&snapshot, &rules);
+        assert_eq!(bot.last_search.reused, 0);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+
+        // garbage the last search didn't know about
+        game.apply(&action);
+        let mut snapshot = game.snapshot().unwrap();
+        snapshot.incoming_garbage[0] += 2;
+        bot.suggest_action(&snapshot, &rules);
+        assert_eq!(bot.last_search.reused, 0);
+    }
+    #[test]
+    fn test_transpositions() {
+        let rules = Ruleset::BOTRIS;
+        // two Os side by side can go down in either order
+        let snapshot = GameSnapshot {
+            falling_piece: FallingPiece::new(Piece::O),
+            held: Piece::O,
+            can_hold: true,
+            queue: [Piece::O; 5].to_vec(),
+            ..Default::default()
+        };
+        let mut bot = shallow();
+        let action = bot.suggest_action(&snapshot, &rules);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+        assert!(bot.last_search.transpositions > 0);
+
+        // no depth keeps two nodes of the same state.
+        // a T then the held I leaves the same board and hold as the I then the T
+        let placement = *move_gen(&EMPTY_BOARD, Piece::O, &rules).iter().min().unwrap();
+        let root = EvaledPlacementNode::new(
+            &EMPTY_BOARD,
+            placement,
+            Piece::I,
+            None,
+            Some(BoardData::default()),
+            &DefaultEval {},
+            &rules,
+            Some(Piece::T),
+        )
+        .unwrap();
+        let mut levels: [BTreeSet<_>; 3] = Default::default();
+        levels[0].insert(root);
+        let mut search = SearchRun::new(None, None, 0);
+        assert!(bot.expand_depths(&mut levels, 1..=2, &[Piece::T; 3], &rules, &mut search));
+        assert!(search.transpositions > 0);
+        for level in &levels {
+            let states: AHashSet<_> = level.iter().map(|node| StateKey::of(node)).collect();
+            assert_eq!(states.len(), level.len());
+        }
+        // parents share most of their children, but each still keeps `branching` states of its own
+        let kept: Vec<_> =
+            levels[1].iter().flat_map(|parent| &search.expanded[&parent.id()]).collect();
+        assert_eq!(kept.len(), levels[1].len() * bot.branching);
+        let states: AHashSet<_> = kept.iter().map(|node| StateKey::of(node)).collect();
+        assert_eq!(states.len(), kept.len());
+    }
+
+    #[test]
+    fn test_threads() {
+        let rules = Ruleset::BOTRIS;
+        let mut game = Game::new(5, rules);
+        game.apply(&[Command::Hold]);
+        let snapshot = game.snapshot().unwrap();
+        let mut serial = shallow();
+        let mut parallel = shallow();
+        parallel.set_threads(4);
+        let action = serial.suggest_action(&snapshot, &rules);
+        assert_eq!(parallel.suggest_action(&snapshot, &rules), action);
+        assert_eq!(parallel.last_search.nodes, serial.last_search.nodes);
+    }
+    #[test]
+    fn test_samples() {
+        let rules = Ruleset::BOTRIS;
+        let mut game = Game::new(9, rules);
+        game.apply(&[Command::Hold]);
+        let snapshot = game.snapshot().unwrap();
+        let mut bots = [shallow(), shallow()];
+        for bot in &mut bots {
+            bot.samples = 3;
+            bot.seed = 1;
+            bot.set_threads(4);
+        }
+        let action = bots[0].suggest_action(&snapshot, &rules);
+        assert_eq!(bots[0].last_search.samples, 3);
+        // guesses are averaged unless asked not to
+        let mut default = shallow();
+        default.suggest_action(&snapshot, &rules);
+        assert!(default.last_search.samples > 1);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+        // the same guesses for the same seed
+        assert_eq!(bots[1].suggest_action(&snapshot, &rules), action);
+        assert_eq!(bots[1].last_search.nodes, bots[0].last_search.nodes);
+    }
 }
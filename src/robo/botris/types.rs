@@ -66,7 +66,7 @@ pub struct BotInfo {
     pub developers: Vec<Developer>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Piece {
     I,
     O,
@@ -135,6 +135,8 @@ pub enum Command {
     SonicRight,
     RotateCw,
     RotateCcw,
+    /// not part of the Botris protocol. see `Ruleset::allow_180`
+    Rotate180,
     Drop,
     SonicDrop,
     HardDrop,
@@ -150,6 +152,7 @@ impl Display for Command {
             Command::SonicRight => ">>",
             Command::RotateCw => "CW",
             Command::RotateCcw => "CCW",
+            Command::Rotate180 => "180",
             Command::Drop => "v",
             Command::SonicDrop => "V",
             Command::HardDrop => "!",
@@ -164,7 +167,7 @@ impl std::fmt::Debug for Command {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ClearName {
     #[serde(rename = "Single")]
     Single,
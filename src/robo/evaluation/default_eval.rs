@@ -4,7 +4,7 @@ use ordered_float::OrderedFloat;
 use owo_colors::OwoColorize;
 
 use super::Evaluate;
-use crate::tetris_core::engine::{BitBoard, BoardData, BITBOARD_HEIGHT};
+use crate::tetris_core::engine::{BitBoard, BoardData, Column, BITBOARD_HEIGHT};
 
 struct DefaultEvalData<'a> {
     board: &'a BitBoard,
@@ -159,7 +159,7 @@ impl DefaultEval {
     fn holes(DefaultEvalData { board, .. }: &DefaultEvalData) -> f32 {
         let mut score = 0.0;
         for mut col in board.cols {
-            if col == u32::MAX {
+            if col == Column::MAX {
                 continue;
             }
             col >>= col.trailing_ones();
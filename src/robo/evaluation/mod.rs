@@ -5,7 +5,8 @@ use ordered_float::OrderedFloat;
 
 use crate::tetris_core::engine::{BitBoard, BoardData};
 
-pub trait Evaluate {
+/// `Sync` so a search can evaluate nodes on several threads
+pub trait Evaluate: Sync {
     fn eval(&self, board: &BitBoard, board_data: &BoardData, verbose: bool) -> OrderedFloat<f32>;
     // fn eval_verbose(&self, frame: &Frame) -> OrderedFloat<f32>;
 }
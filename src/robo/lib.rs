@@ -1,6 +1,10 @@
 pub mod akirobo;
 pub mod botris;
 pub mod evaluation;
+pub mod mcts;
 pub mod movegen;
+pub mod search;
 pub mod searchtree;
 pub mod tetris_core;
+pub mod validation;
+pub mod versus;
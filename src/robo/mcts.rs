@@ -0,0 +1,365 @@
+//! Monte Carlo tree search, as an alternative to the beam search of `Akirobo`.
+//! Every iteration guesses the pieces past the visible queue, walks down the moves that look best so far
+//! and expands one more board, so the tree grows towards the lines worth looking at.
+
+use std::time::Instant;
+
+use ahash::AHashMap;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    botris::types::{Command, Piece},
+    evaluation::{default_eval::DefaultEval, Evaluate},
+    movegen::{move_gen, move_gen_snapshot, Move, Placement},
+    search::{Search, SearchStats, TimeBudget, DEATH_WIGGLE},
+    tetris_core::{
+        engine::{BitBoard, BoardData},
+        ruleset::Ruleset,
+        snapshot::GameSnapshot,
+    },
+};
+
+/// deepest the tree grows, in pieces placed
+const MAX_DEPTH: usize = 12;
+/// what a board that topped out is worth. finite, so it can be averaged with boards that didn't.
+const TOP_OUT_SCORE: f32 = -1.0e6;
+
+pub struct Mcts<E: Evaluate = DefaultEval> {
+    evaluator: E,
+    /// iterations per move. the search also stops at `budget`, whichever comes first.
+    pub iterations: usize,
+    pub budget: TimeBudget,
+    /// how much an untried move is favoured, in evaluation points
+    pub exploration: f32,
+    /// seeds the guessed pieces
+    pub seed: u64,
+    /// stats of the last `suggest_action`
+    pub last_search: SearchStats,
+}
+
+/// A board reached by placing pieces from the root.
+struct Node {
+    board: BitBoard,
+    data: BoardData,
+    held: Piece,
+    /// pieces placed since the root
+    depth: usize,
+    topped_out: bool,
+    /// the evaluation of this board
+    score: f32,
+    /// the evaluation of the best line below, averaged over the pieces that came up next
+    value: f32,
+    visits: u32,
+    /// the placements of each piece that has come up next here
+    branches: Vec<Branch>,
+}
+
+struct Branch {
+    piece: Piece,
+    visits: u32,
+    /// what is placed, what is left in hold, and the node it leads to
+    edges: Vec<(Placement, Piece, usize)>,
+}
+
+impl Mcts {
+    pub fn new() -> Self {
+        Mcts::with_evaluator(DefaultEval {})
+    }
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: Evaluate> Mcts<E> {
+    pub fn with_evaluator(evaluator: E) -> Self {
+        Mcts {
+            evaluator,
+            iterations: 2000,
+            budget: TimeBudget::default(),
+            exploration: 2.0,
+            seed: 0,
+            last_search: SearchStats::default(),
+        }
+    }
+
+    /// the most visited move after `self.iterations` iterations or `self.budget`, whichever comes first
+    pub fn suggest_action(&mut self, genesis: &GameSnapshot, rules: &Ruleset) -> Vec<Command> {
+        let start_time = Instant::now();
+        let deadline = self.budget.deadline(start_time);
+        let data = BoardData {
+            b2b: genesis.b2b,
+            b2b_chain: 0,
+            combo: genesis.combo,
+            cummulative_attack: 0,
+            incoming: genesis.incoming_garbage,
+            simulated_garbage: 0,
+            garbage_holes: genesis.garbage_holes,
+        };
+        let mut nodes = vec![Node {
+            board: genesis.matrix,
+            data,
+            held: genesis.held,
+            depth: 0,
+            topped_out: false,
+            score: 0.0,
+            value: 0.0,
+            visits: 0,
+            branches: vec![],
+        }];
+
+        // the root is the only node that can't always hold, and the only one that needs actions
+        let mut action_lookup = AHashMap::new();
+        let mut moves = move_gen_snapshot(genesis, rules);
+        // ties keep whichever came first, so the order has to be the same every time
+        moves.sort_unstable_by_key(|candidate| (candidate.placement, candidate.held));
+        let placements: Vec<_> =
+            moves.iter().map(|candidate| (candidate.placement, candidate.held)).collect();
+        let edges =
+            self.add_children(&mut nodes, 0, &placements, genesis.queue.first().copied(), rules);
+        nodes[0].branches.push(Branch { piece: genesis.falling_piece.piece, visits: 0, edges });
+        for Move { placement, held, action, .. } in moves {
+            action_lookup.insert((placement, held), action);
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut iterations = 0;
+        let mut timed_out = false;
+        while iterations < self.iterations {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out = true;
+                break;
+            }
+            let mut queue = vec![genesis.falling_piece.piece];
+            queue.extend(genesis.sample_queue(&mut rng, MAX_DEPTH + 1));
+            self.iterate(&mut nodes, &queue, rules);
+            iterations += 1;
+        }
+
+        self.last_search = SearchStats {
+            depth: nodes.iter().map(|node| node.depth).max().unwrap_or(0),
+            nodes: nodes.len() - 1,
+            reused: 0,
+            transpositions: 0,
+            samples: 0,
+            iterations,
+            elapsed: start_time.elapsed(),
+            timed_out,
+        };
+
+        // the move looked at the most is the one the search trusts the most
+        let best = nodes[0].branches[0].edges.iter().max_by(|(.., a), (.., b)| {
+            (nodes[*a].visits, nodes[*a].value)
+                .partial_cmp(&(nodes[*b].visits, nodes[*b].value))
+                .unwrap()
+        });
+        match best {
+            Some((placement, held, _)) => action_lookup[&(*placement, *held)].to_owned(),
+            None => DEATH_WIGGLE.to_vec(),
+        }
+    }
+
+    /// Walks down from the root to a board that hasn't had `queue`'s next piece placed on it yet,
+    /// places it every way it fits, and backs up what the new boards are worth.
+    fn iterate(&self, nodes: &mut Vec<Node>, queue: &[Piece], rules: &Ruleset) {
+        // each node on the way down, and the branch taken from it
+        let mut path = vec![];
+        let mut current = 0;
+        loop {
+            let node = &nodes[current];
+            if node.topped_out || node.depth >= MAX_DEPTH {
+                path.push((current, None));
+                break;
+            }
+            let piece = queue[node.depth];
+            if let Some(known) = node.branches.iter().position(|branch| branch.piece == piece) {
+                path.push((current, Some(known)));
+                match self.select(nodes, &node.branches[known]) {
+                    Some(child) => current = child,
+                    // the piece fits nowhere
+                    None => break,
+                }
+                continue;
+            }
+
+            let (board, held, depth) = (node.board, node.held, node.depth);
+            let mut placements: Vec<_> = move_gen(&board, piece, rules)
+                .into_iter()
+                .map(|placement| (placement, held))
+                .collect();
+            if held != piece {
+                placements.extend(
+                    move_gen(&board, held, rules).into_iter().map(|placement| (placement, piece)),
+                );
+            }
+            // ties keep whichever came first, so the order has to be the same every time
+            placements.sort_unstable();
+            let edges = self.add_children(
+                nodes,
+                current,
+                &placements,
+                queue.get(depth + 1).copied(),
+                rules,
+            );
+            nodes[current].branches.push(Branch { piece, visits: 0, edges });
+            path.push((current, Some(nodes[current].branches.len() - 1)));
+            break;
+        }
+
+        for &(id, branch) in path.iter().rev() {
+            let node = &mut nodes[id];
+            node.visits += 1;
+            if let Some(branch) = branch {
+                node.branches[branch].visits += 1;
+            }
+            nodes[id].value = value(nodes, id);
+        }
+    }
+
+    /// the child of `branch` most worth looking into: good so far, or not looked at much
+    fn select(&self, nodes: &[Node], branch: &Branch) -> Option<usize> {
+        let explore = (branch.visits as f32 + 1.0).ln();
+        branch.edges.iter().map(|&(.., child)| child).max_by(|&a, &b| {
+            let priority = |child: usize| {
+                let node = &nodes[child];
+                node.value + self.exploration * (explore / (node.visits as f32 + 1.0)).sqrt()
+            };
+            priority(a).partial_cmp(&priority(b)).unwrap()
+        })
+    }
+
+    /// evaluates a child of `parent` for each of `placements` and returns the edges to them
+    fn add_children(
+        &self,
+        nodes: &mut Vec<Node>,
+        parent: usize,
+        placements: &[(Placement, Piece)],
+        next_piece: Option<Piece>,
+        rules: &Ruleset,
+    ) -> Vec<(Placement, Piece, usize)> {
+        let (board, data, depth) = (nodes[parent].board, nodes[parent].data, nodes[parent].depth);
+        let mut edges = vec![];
+        for &(placement, held) in placements {
+            let Ok((board, data, clear)) = board.lock_piece(
+                &placement.piece_location,
+                placement.spin,
+                data,
+                rules,
+                next_piece,
+            ) else {
+                continue;
+            };
+            let topped_out = clear.top_out.is_some();
+            let score = match topped_out {
+                true => TOP_OUT_SCORE,
+                false => self.evaluator.eval(&board, &data, false).0,
+            };
+            nodes.push(Node {
+                board,
+                data,
+                held,
+                depth: depth + 1,
+                topped_out,
+                score,
+                value: score,
+                visits: 0,
+                branches: vec![],
+            });
+            edges.push((placement, held, nodes.len() - 1));
+        }
+        edges
+    }
+}
+
+impl<E: Evaluate> Search for Mcts<E> {
+    fn suggest_action(&mut self, genesis: &GameSnapshot, rules: &Ruleset) -> Vec<Command> {
+        Mcts::suggest_action(self, genesis, rules)
+    }
+
+    fn last_search(&self) -> SearchStats {
+        self.last_search
+    }
+}
+
+/// The best child of each branch, averaged over how often each branch's piece came up.
+/// A node nothing has been placed on yet is worth its own evaluation.
+fn value(nodes: &[Node], id: usize) -> f32 {
+    let node = &nodes[id];
+    let visits: u32 = node.branches.iter().map(|branch| branch.visits).sum();
+    if visits == 0 {
+        return node.score;
+    }
+    node.branches
+        .iter()
+        .map(|branch| {
+            let best = branch
+                .edges
+                .iter()
+                .map(|&(.., child)| nodes[child].value)
+                .fold(TOP_OUT_SCORE, f32::max);
+            best * branch.visits as f32
+        })
+        .sum::<f32>()
+        / visits as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::Mcts;
+    use crate::{
+        botris::types::{Command, Piece},
+        search::{TimeBudget, DEATH_WIGGLE},
+        tetris_core::{
+            engine::BitBoard, game::Game, piece::FallingPiece, ruleset::Ruleset,
+            snapshot::GameSnapshot,
+        },
+        validation::replay_action,
+    };
+
+    #[test]
+    fn test_mcts() {
+        let rules = Ruleset::BOTRIS;
+        let mut game = Game::new(2, rules);
+        game.apply(&[Command::Hold]);
+        let snapshot = game.snapshot().unwrap();
+        let mut bots = [Mcts::new(), Mcts::new()];
+        for bot in &mut bots {
+            bot.iterations = 300;
+            bot.seed = 7;
+        }
+        let action = bots[0].suggest_action(&snapshot, &rules);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+        let stats = bots[0].last_search;
+        assert_eq!(stats.iterations, 300);
+        assert!(!stats.timed_out);
+        assert!(stats.depth > 1);
+        // the same guesses for the same seed
+        assert_eq!(bots[1].suggest_action(&snapshot, &rules), action);
+        assert_eq!(bots[1].last_search.nodes, stats.nodes);
+
+        bots[0].budget = TimeBudget::PerMove(Default::default());
+        let action = bots[0].suggest_action(&snapshot, &rules);
+        assert!(bots[0].last_search.timed_out);
+        assert_eq!(bots[0].last_search.iterations, 0);
+        assert!(replay_action(&snapshot, &action, &rules).is_ok());
+    }
+
+    #[test]
+    fn test_topped_out() {
+        let rules = Ruleset::BOTRIS;
+        let snapshot = GameSnapshot {
+            matrix: BitBoard::from_strs(&["    [][][][][][][][]"; 21]),
+            falling_piece: FallingPiece::new(Piece::T),
+            held: Piece::I,
+            can_hold: true,
+            queue: vec![Piece::O],
+            ..Default::default()
+        };
+        let mut bot = Mcts::new();
+        bot.iterations = 10;
+        // the same answer as the beam search when the piece fits nowhere
+        assert_eq!(bot.suggest_action(&snapshot, &rules), DEATH_WIGGLE);
+    }
+}
@@ -1,128 +1,697 @@
+use std::{
+    array,
+    hash::{Hash, Hasher},
+};
+
 use ahash::{AHashMap, AHashSet};
 
 use crate::{
     botris::types::{Command, Piece},
     tetris_core::{
-        engine::BitBoard,
+        engine::{BitBoard, Column, BITBOARD_HEIGHT},
         piece::{FallingPiece, PieceCoords},
+        rotation::{Kick, MAX_KICKS},
+        ruleset::{Gravity, Ruleset},
+        snapshot::GameSnapshot,
+        spin::{self, Spin, SpinRule},
     },
 };
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub struct Placement {
+    /// sorted, so rotations filling the same cells (I, O, S and Z) give the same placement
     pub piece_location: PieceCoords,
-    pub all_spin: bool,
+    pub spin: Spin,
+}
+
+impl Hash for Placement {
+    /// the cells packed into one word. hashing them a cell at a time was a good part of what `move_gen` cost.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let cells = self
+            .piece_location
+            .iter()
+            .fold(0u64, |acc, &(y, x)| acc << 16 | (y as u8 as u64) << 8 | x as u8 as u64);
+        state.write_u64(cells);
+        self.spin.hash(state);
+    }
 }
 
 impl Placement {
-    pub fn new(board: &BitBoard, falling_piece: &FallingPiece) -> Placement {
-        let all_spin = [(0, 1), (1, 0), (-1, 0)].iter().all(|&(dx, dy)| {
-            let mut nudged = *falling_piece;
-            nudged.shift(dy, dx);
-            board.collides(&nudged)
-        });
-        Placement { piece_location: falling_piece.coords, all_spin }
+    /// `falling_piece` locking where it is. `last_kick` is the kick used if its last move was a rotation.
+    pub fn new<const W: usize>(
+        board: &BitBoard<W>,
+        falling_piece: &FallingPiece,
+        last_kick: Option<Kick>,
+        rules: &Ruleset,
+    ) -> Placement {
+        Placement::spun(falling_piece, spin::classify(board, falling_piece, last_kick, rules))
+    }
+
+    fn spun(falling_piece: &FallingPiece, spin: Spin) -> Placement {
+        let mut piece_location = falling_piece.coords;
+        piece_location.sort_unstable();
+        Placement { piece_location, spin }
     }
 }
 
-pub fn move_gen_with_action(board: &BitBoard, piece: Piece) -> AHashMap<Placement, Vec<Command>> {
+/// everything the piece can do before it locks. among equally short actions, the one whose
+/// first differing command comes earlier here wins.
+fn moves(rules: &Ruleset) -> &'static [Command] {
     use Command::*;
-    let rotation_sets = [vec![], vec![RotateCw], vec![RotateCcw], vec![RotateCcw, RotateCcw]];
-
-    let initial_falling_piece = FallingPiece::new(piece);
-    if board.collides(&initial_falling_piece) {
-        return AHashMap::new();
-    }
-
-    let mut rotated_pieces = AHashMap::new();
-    for rotation_set in rotation_sets {
-        if let Some(rotated_piece) = board.try_commands(&initial_falling_piece, &rotation_set) {
-            rotated_pieces.insert(rotated_piece, rotation_set.clone());
-        }
-    }
-    let mut moved_and_soniced = AHashMap::new();
-    for (piece, mut action) in rotated_pieces {
-        for direction in [MoveLeft, MoveRight] {
-            let mut moving_piece = piece;
-            let mut moving_action = action.clone();
-            while let Some(moved_piece) = board.try_command(&moving_piece, direction) {
-                moving_piece = moved_piece;
-                moving_action.push(direction);
-                let mut val = moving_action.clone();
-                val.push(SonicDrop);
-                moved_and_soniced.insert(board.force_sonic_drop(&moving_piece), val);
-            }
+    match rules.allow_180 {
+        true => &[
+            MoveLeft, MoveRight, SonicLeft, SonicRight, RotateCw, RotateCcw, Rotate180, SonicDrop,
+            Drop,
+        ],
+        false => {
+            &[MoveLeft, MoveRight, SonicLeft, SonicRight, RotateCw, RotateCcw, SonicDrop, Drop]
         }
-        action.push(SonicDrop);
-        moved_and_soniced.insert(board.force_sonic_drop(&piece), action);
     }
+}
 
-    let mut generated = AHashMap::new();
+/// A state the piece can reach, and the state and command that first reached it.
+struct Reached {
+    falling_piece: FallingPiece,
+    /// the spin if the piece locks right here. none unless it's resting.
+    spin: Spin,
+    lock_resets: u32,
+    parent: Option<(usize, Command)>,
+}
 
-    for (piece, action) in &moved_and_soniced {
-        generated.insert(Placement::new(board, piece), action.clone());
+/// Breadth first search over every (rotation, x, y) the piece can reach from spawn, and whether it
+/// would lock as a spin there, since the same spot can be reached with and without one.
+/// States are in the order they were found, so the first is the spawn. Since commands are tried
+/// in `moves` order, each state is first reached by its shortest, earliest ordered action.
+/// With a `Ruleset::lock_reset_limit`, a state is found again whenever a path to it uses fewer resets,
+/// and a resting piece out of resets locks instead of moving.
+/// `found` sees the states so far each time one is added, and stops the search by returning true.
+fn flood_fill<const W: usize>(
+    board: &BitBoard<W>,
+    piece: Piece,
+    rules: &Ruleset,
+    mut found: impl FnMut(&[Reached]) -> bool,
+) -> Vec<Reached> {
+    let spawn = FallingPiece::spawn(piece, W, rules.visible_height);
+    if board.collides(&spawn) {
+        return vec![];
+    }
+    let spawn = board.apply_gravity(&spawn, rules);
+    let lock_spin = |falling_piece: &FallingPiece, last_kick| match is_resting(board, falling_piece)
+    {
+        true => spin::classify(board, falling_piece, last_kick, rules),
+        false => Spin::None,
+    };
+    let spin = lock_spin(&spawn, None);
+    let mut reached = vec![Reached { falling_piece: spawn, spin, lock_resets: 0, parent: None }];
+    if found(&reached) {
+        return reached;
     }
-    for spin in [RotateCcw, RotateCw] {
-        for (piece, action) in &moved_and_soniced {
-            if let Some(spun) = board.try_command(piece, spin) {
-                let placement = Placement::new(board, &board.force_sonic_drop(&spun));
-                let mut val = action.clone();
-                val.push(spin);
-                generated.entry(placement).or_insert(val);
+    // the fewest resets each state has been reached with, by rotation, cell, then spin.
+    // every state the piece can be in has a slot, which beats hashing them.
+    const SPINS: usize = Spin::AllSpin as usize + 1;
+    let index = |falling_piece: &FallingPiece, spin: Spin| {
+        let (y, x) = falling_piece.coords[0];
+        let cell =
+            falling_piece.rotation as usize * BITBOARD_HEIGHT * W + y as usize * W + x as usize;
+        cell * SPINS + spin as usize
+    };
+    let mut seen = vec![u32::MAX; 4 * BITBOARD_HEIGHT * W * SPINS];
+    seen[index(&spawn, spin)] = 0;
+    // rows above the stack, where only the walls get in the way
+    let stack_height = board
+        .cols
+        .iter()
+        .map(|column| (Column::BITS - column.leading_zeros()) as i8)
+        .max()
+        .unwrap_or(0);
+    // how far any turn can move a cell down
+    let turn_depth = rules.rotation_system.turns()[piece as usize]
+        .iter()
+        .flatten()
+        .flat_map(|turn| turn.as_slice().iter().flatten())
+        .map(|&(dy, _)| -dy)
+        .max()
+        .unwrap_or(0);
+    let in_air = |falling_piece: &FallingPiece| {
+        let bottom = falling_piece.coords.iter().map(|&(y, _)| y).min().unwrap();
+        bottom - turn_depth >= stack_height
+    };
+    let mut next = 0;
+    while let Some(&Reached { falling_piece, lock_resets, parent, .. }) = reached.get(next) {
+        next += 1;
+        // moving a piece that's touching the stack restarts its lock delay
+        let lock_resets = lock_resets + is_resting(board, &falling_piece) as u32;
+        if rules.lock_reset_limit.is_some_and(|limit| lock_resets > limit) {
+            continue;
+        }
+        // a piece soft dropped through the air can do anything else a row up first, with an action
+        // as short that's ordered earlier, so all that's left is to keep dropping
+        let dropped_in_air = matches!(parent, Some((_, Command::Drop))) && in_air(&falling_piece);
+        let commands = match dropped_in_air {
+            true => &[Command::Drop],
+            false => moves(rules),
+        };
+        for &command in commands {
+            let Some((moved, kick)) = board.try_command_with_kick(&falling_piece, command, rules)
+            else {
+                continue;
+            };
+            let spin = lock_spin(&moved, kick);
+            let fewest = &mut seen[index(&moved, spin)];
+            let fewer_resets = match *fewest {
+                u32::MAX => true,
+                fewest => rules.lock_reset_limit.is_some() && lock_resets < fewest,
+            };
+            if fewer_resets {
+                *fewest = lock_resets;
+                reached.push(Reached {
+                    falling_piece: moved,
+                    spin,
+                    lock_resets,
+                    parent: Some((next - 1, command)),
+                });
+                if found(&reached) {
+                    return reached;
+                }
             }
         }
     }
+    reached
+}
 
+fn is_resting<const W: usize>(board: &BitBoard<W>, falling_piece: &FallingPiece) -> bool {
+    let mut below = *falling_piece;
+    below.shift(-1, 0);
+    board.collides(&below)
+}
+
+/// The inputs that get the piece to a placement.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Path {
+    pub commands: Vec<Command>,
+    /// commands made while the piece was touching the stack, each restarting its lock delay
+    pub lock_resets: u32,
+}
+
+/// Every placement the piece can lock in, with the path that gets there in the fewest commands
+/// without going over the ruleset's lock reset limit. Soft drops (`Drop`) are used where the piece
+/// has to stop partway down.
+/// Ties are broken by `moves` order, so the same board always gets the same paths.
+/// Paths don't end with `HardDrop` since the server drops the piece after every action,
+/// so they can stop anywhere above their placement.
+/// The placements come from `move_gen`, so the search stops as soon as each of them has a path.
+pub fn move_gen_with_path<const W: usize>(
+    board: &BitBoard<W>,
+    piece: Piece,
+    rules: &Ruleset,
+) -> AHashMap<Placement, Path> {
+    let placements = move_gen(board, piece, rules);
+    let mut generated = AHashMap::with_capacity(placements.len());
+    flood_fill(board, piece, rules, |reached| {
+        let state = reached.last().unwrap();
+        let dropped = board.force_sonic_drop(&state.falling_piece);
+        let placement = match dropped == state.falling_piece {
+            true => Placement::spun(&dropped, state.spin),
+            false => Placement::new(board, &dropped, None, rules),
+        };
+        if placements.contains(&placement) {
+            generated.entry(placement).or_insert_with(|| {
+                let mut commands = vec![];
+                let mut parent = state.parent;
+                while let Some((index, command)) = parent {
+                    commands.push(command);
+                    parent = reached[index].parent;
+                }
+                commands.reverse();
+                Path { commands, lock_resets: state.lock_resets }
+            });
+        }
+        generated.len() == placements.len()
+    });
     generated
 }
 
-pub fn move_gen(board: &BitBoard, piece: Piece) -> AHashSet<Placement> {
+/// `move_gen_with_path`, without the lock resets
+pub fn move_gen_with_action<const W: usize>(
+    board: &BitBoard<W>,
+    piece: Piece,
+    rules: &Ruleset,
+) -> AHashMap<Placement, Vec<Command>> {
+    move_gen_with_path(board, piece, rules)
+        .into_iter()
+        .map(|(placement, path)| (placement, path.commands))
+        .collect()
+}
+
+/// A placement reachable from a `GameSnapshot`, with or without holding first.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Move {
+    pub placement: Placement,
+    /// the piece that gets placed
+    pub piece: Piece,
+    /// the piece in hold after this move
+    pub held: Piece,
+    /// starts with `Hold` if the held piece is placed
+    pub action: Vec<Command>,
+    pub lock_resets: u32,
+}
+
+/// Every move the falling piece of `snapshot` can make, then every move of the held piece if it can
+/// be held. Holding a piece for the same piece would only repeat its placements with a longer action.
+pub fn move_gen_snapshot(snapshot: &GameSnapshot, rules: &Ruleset) -> Vec<Move> {
+    let board = &snapshot.matrix;
+    let current = snapshot.falling_piece.piece;
+    let mut moves: Vec<Move> = move_gen_with_path(board, current, rules)
+        .into_iter()
+        .map(|(placement, Path { commands, lock_resets })| Move {
+            placement,
+            piece: current,
+            held: snapshot.held,
+            action: commands,
+            lock_resets,
+        })
+        .collect();
+    if snapshot.can_hold && snapshot.held != current {
+        moves.extend(move_gen_with_path(board, snapshot.held, rules).into_iter().map(
+            |(placement, Path { commands, lock_resets })| Move {
+                placement,
+                piece: snapshot.held,
+                held: current,
+                action: [Command::Hold].into_iter().chain(commands).collect(),
+                lock_resets,
+            },
+        ));
+    }
+    moves
+}
+
+/// Every placement the piece can lock in, found by `flood_fill` one state at a time.
+/// Slow, but simple enough to check `move_gen` against.
+pub fn move_gen_reference<const W: usize>(
+    board: &BitBoard<W>,
+    piece: Piece,
+    rules: &Ruleset,
+) -> AHashSet<Placement> {
+    flood_fill(board, piece, rules, |_| false)
+        .iter()
+        .filter(|state| is_resting(board, &state.falling_piece))
+        .map(|state| Placement::spun(&state.falling_piece, state.spin))
+        .collect()
+}
+
+/// One rotation of the piece, as cells relative to the bottom left corner of its bounding box.
+/// Positions in `move_gen` are where that corner is.
+#[derive(Clone, Copy)]
+struct Shape {
+    cells: PieceCoords,
+    width: usize,
+}
+
+impl Shape {
+    /// the shape of `falling_piece` and where its corner is
+    fn of(falling_piece: &FallingPiece) -> (Shape, (i8, i8)) {
+        let (corner_y, corner_x) = Shape::corner(falling_piece);
+        let mut cells = falling_piece.coords.map(|(y, x)| (y - corner_y, x - corner_x));
+        // moving every cell by the same amount keeps them sorted, so placements don't have to sort
+        cells.sort_unstable();
+        let width = cells.iter().map(|&(_, dx)| dx as usize + 1).max().unwrap();
+        (Shape { cells, width }, (corner_y, corner_x))
+    }
+
+    /// the bottom left corner of the piece's bounding box
+    fn corner(falling_piece: &FallingPiece) -> (i8, i8) {
+        falling_piece
+            .coords
+            .iter()
+            .fold((i8::MAX, i8::MAX), |(min_y, min_x), &(y, x)| (min_y.min(y), min_x.min(x)))
+    }
+
+    /// bit y of column x is set where the shape collides with its corner at (y, x)
+    fn collision_map<const W: usize>(&self, board: &BitBoard<W>) -> [Column; W] {
+        let mut map = [Column::MAX; W];
+        for (x, column) in map.iter_mut().enumerate().take((W + 1).saturating_sub(self.width)) {
+            *column =
+                self.cells.iter().fold(0, |acc, &(dy, dx)| acc | board.cols[x + dx as usize] >> dy);
+        }
+        map
+    }
+}
+
+/// a command turning the piece from one rotation to another, and how each of its kicks moves the corner
+struct Rotation {
+    from: usize,
+    to: usize,
+    half_turn: bool,
+    corner_moves: [(i8, i8); MAX_KICKS],
+    kicks: usize,
+}
+
+const EMPTY_ROTATION: Rotation =
+    Rotation { from: 0, to: 0, half_turn: false, corner_moves: [(0, 0); MAX_KICKS], kicks: 0 };
+
+/// rotation commands the ruleset allows
+fn spins(rules: &Ruleset) -> &'static [Command] {
     use Command::*;
-    let rotation_sets = [vec![], vec![RotateCw], vec![RotateCcw], vec![RotateCcw, RotateCcw]];
+    match rules.allow_180 {
+        true => &[RotateCw, RotateCcw, Rotate180],
+        false => &[RotateCw, RotateCcw],
+    }
+}
 
-    let initial_falling_piece = FallingPiece::new(piece);
-    if board.collides(&initial_falling_piece) {
-        return AHashSet::new();
+/// `column` moved up by `dy` rows (down if negative)
+fn shift_rows(column: Column, dy: i8) -> Column {
+    if dy >= 0 {
+        column << dy
+    } else {
+        column >> -dy
     }
+}
+
+/// every position `reached` can soft drop to, given the positions that are `free`
+fn drop_fill(mut reached: Column, mut free: Column) -> Column {
+    for distance in [1, 2, 4, 8, 16, 32] {
+        reached |= free & (reached >> distance);
+        free &= free >> distance;
+    }
+    reached
+}
 
-    let mut rotated_pieces = AHashSet::new();
-    for rotation_set in rotation_sets {
-        if let Some(rotated_piece) = board.try_commands(&initial_falling_piece, &rotation_set) {
-            rotated_pieces.insert(rotated_piece);
+/// where the positions in `column` end up after gravity
+fn settle(column: Column, free: Column, gravity: Gravity) -> Column {
+    match gravity {
+        Gravity::Zero => column,
+        Gravity::Instant => drop_fill(column, free) & (!free << 1 | 1),
+    }
+}
+
+/// Turns each of `sources` with the first kick that fits, and passes the kick, column and positions
+/// each kick turned to `turned`, settled by `gravity`.
+fn turn<const W: usize>(
+    sources: &[Column; W],
+    rotation: &Rotation,
+    free_to: &[Column; W],
+    gravity: Gravity,
+    mut turned: impl FnMut(usize, usize, Column),
+) {
+    let Rotation { corner_moves, kicks, .. } = rotation;
+    for (x, &source) in sources.iter().enumerate() {
+        let mut remaining = source;
+        for (kick, &(dy, dx)) in corner_moves[..*kicks].iter().enumerate() {
+            let kicked_x = x as i8 + dx;
+            if remaining == 0 || !(0..W as i8).contains(&kicked_x) {
+                continue;
+            }
+            let kicked_x = kicked_x as usize;
+            let fits = remaining & shift_rows(free_to[kicked_x], -dy);
+            turned(kick, kicked_x, settle(shift_rows(fits, dy), free_to[kicked_x], gravity));
+            remaining &= !fits;
         }
     }
-    let mut moved_and_soniced = AHashSet::new();
-    for piece in rotated_pieces {
-        for direction in [MoveLeft, MoveRight] {
-            let mut moving_piece = piece;
-            while let Some(moved_piece) = board.try_command(&moving_piece, direction) {
-                moving_piece = moved_piece;
-                moved_and_soniced.insert(board.force_sonic_drop(&moving_piece));
+}
+
+/// Where a sonic shift `dx` (-1 for left) takes each of `sources`: a column at a time, settling after
+/// each, until the next column is blocked. Sources that can't move at all don't end anywhere.
+fn slide<const W: usize>(
+    sources: &[Column; W],
+    free: &[Column; W],
+    gravity: Gravity,
+    dx: isize,
+) -> [Column; W] {
+    let mut ends = [0; W];
+    // positions that moved into the current column
+    let mut arrived = 0;
+    for i in 0..W {
+        let x = if dx < 0 { W - 1 - i } else { i };
+        let next_free =
+            x.checked_add_signed(dx).filter(|&next| next < W).map_or(0, |next| free[next]);
+        ends[x] |= arrived & !next_free;
+        arrived = settle((arrived | sources[x]) & next_free, next_free, gravity);
+    }
+    ends
+}
+
+/// Every placement the piece can lock in.
+/// Reachable positions are kept as one bitmask per rotation and column, and filled in whole columns
+/// at a time against each rotation's collision map until nothing new is reached.
+/// With a `Ruleset::lock_reset_limit`, the fill is repeated once per reset, each time moving on from
+/// the positions touching the stack, so positions past the limit are never reached.
+pub fn move_gen<const W: usize>(
+    board: &BitBoard<W>,
+    piece: Piece,
+    rules: &Ruleset,
+) -> AHashSet<Placement> {
+    let spawn = FallingPiece::spawn(piece, W, rules.visible_height);
+    if board.collides(&spawn) {
+        return AHashSet::new();
+    }
+
+    // each rotation turned in place from spawn, to get its shape and cell order
+    let mut rotated = [spawn; 4];
+    let mut shapes = [Shape { cells: spawn.coords, width: 0 }; 4];
+    let mut corners = [(0, 0); 4];
+    let mut free = [[0; W]; 4];
+    for rotation in 0..4 {
+        rotated[rotation]
+            .turn(rules.rotation_system.offsets(piece, 0, rotation as u8), rotation as u8);
+        (shapes[rotation], corners[rotation]) = Shape::of(&rotated[rotation]);
+        free[rotation] = shapes[rotation].collision_map(board).map(|column| !column);
+    }
+
+    let turns = &rules.rotation_system.turns()[piece as usize];
+    // at most three turns out of each rotation
+    let mut rotations = [EMPTY_ROTATION; 12];
+    let mut rotation_count = 0;
+    for from in 0..4 {
+        for &command in spins(rules) {
+            let to = rotated[from].rotation_after(command) as usize;
+            let rotation = &mut rotations[rotation_count];
+            rotation_count += 1;
+            let half_turn = command == Command::Rotate180;
+            *rotation = Rotation { from, to, half_turn, ..EMPTY_ROTATION };
+            for offsets in turns[from][to].as_slice() {
+                let mut kicked = rotated[from];
+                kicked.turn(offsets, to as u8);
+                let (y, x) = Shape::corner(&kicked);
+                rotation.corner_moves[rotation.kicks] = (y - corners[from].0, x - corners[from].1);
+                rotation.kicks += 1;
             }
         }
-        moved_and_soniced.insert(board.force_sonic_drop(&piece));
     }
+    let rotations = &rotations[..rotation_count];
+
+    // at 20G only positions resting on something are ever reached, and every step settles again
+    let gravity = rules.gravity;
+    // resting on the floor or the stack
+    let resting = free.map(|free| free.map(|free| !free << 1 | 1));
+    // moving a piece that's touching the stack restarts its lock delay, which only matters with a limit
+    let costly = match rules.lock_reset_limit {
+        Some(_) => resting,
+        None => [[0; W]; 4],
+    };
+    let mut reached = [[0 as Column; W]; 4];
+    let spawn_x = corners[0].1 as usize;
+    reached[0][spawn_x] = settle(1 << corners[0].0, free[0][spawn_x], gravity);
+    // costly positions whose moves have been tried, each spending one more reset
+    let mut stepped = [[0 as Column; W]; 4];
+    let mut resets = 0;
+    // rotations with positions their turns haven't been tried from yet
+    let mut pending = [true, false, false, false];
+    loop {
+        // everything the positions reached so far lead to without another reset
+        while let Some(from) = pending.iter().position(|&pending| pending) {
+            pending[from] = false;
+            let (free_from, costly_from) = (&free[from], &costly[from]);
+            loop {
+                let before = reached[from];
+                let reached = &mut reached[from];
+                for x in 0..W {
+                    reached[x] = drop_fill(reached[x], free_from[x]);
+                }
+                for x in (1..W).rev() {
+                    let sources = reached[x] & !costly_from[x];
+                    reached[x - 1] |= settle(sources & free_from[x - 1], free_from[x - 1], gravity);
+                }
+                for x in 1..W {
+                    let sources = reached[x - 1] & !costly_from[x - 1];
+                    reached[x] |= settle(sources & free_from[x], free_from[x], gravity);
+                }
+                // shifting one cell at a time stops at costly positions, but a sonic shift doesn't
+                if rules.lock_reset_limit.is_some() {
+                    let sources = array::from_fn(|x| reached[x] & !costly_from[x]);
+                    for dx in [-1, 1] {
+                        let ends = slide(&sources, free_from, gravity, dx);
+                        for (reached, end) in reached.iter_mut().zip(ends) {
+                            *reached |= end;
+                        }
+                    }
+                }
+                if *reached == before {
+                    break;
+                }
+            }
+            let sources = array::from_fn(|x| reached[from][x] & !costly[from][x]);
+            for rotation in rotations.iter().filter(|rotation| rotation.from == from) {
+                let to = rotation.to;
+                let before = reached[to];
+                turn(&sources, rotation, &free[to], gravity, |_, x, turned| {
+                    reached[to][x] |= turned
+                });
+                pending[to] |= reached[to] != before;
+            }
+        }
 
-    let mut generated = AHashSet::new();
+        // then every move out of the costly positions reached since the last reset
+        if rules.lock_reset_limit.is_none_or(|limit| resets == limit) {
+            break;
+        }
+        resets += 1;
+        let sources: [[Column; W]; 4] =
+            array::from_fn(|r| array::from_fn(|x| reached[r][x] & costly[r][x] & !stepped[r][x]));
+        let before = reached;
+        for rotation in 0..4 {
+            let (sources, free) = (&sources[rotation], &free[rotation]);
+            let reached = &mut reached[rotation];
+            for x in 0..W {
+                stepped[rotation][x] |= sources[x];
+                if x > 0 {
+                    reached[x - 1] |= settle(sources[x] & free[x - 1], free[x - 1], gravity);
+                }
+                if x + 1 < W {
+                    reached[x + 1] |= settle(sources[x] & free[x + 1], free[x + 1], gravity);
+                }
+            }
+            for dx in [-1, 1] {
+                for (reached, end) in reached.iter_mut().zip(slide(sources, free, gravity, dx)) {
+                    *reached |= end;
+                }
+            }
+        }
+        for rotation @ &Rotation { from, to, .. } in rotations {
+            turn(&sources[from], rotation, &free[to], gravity, |_, x, turned| {
+                reached[to][x] |= turned
+            });
+        }
+        for rotation in 0..4 {
+            pending[rotation] = reached[rotation] != before[rotation];
+        }
+        if pending == [false; 4] {
+            break;
+        }
+    }
+    // positions the piece can still move from. the costly ones not stepped from are out of resets.
+    let movable: [[Column; W]; 4] =
+        array::from_fn(|r| array::from_fn(|x| reached[r][x] & !(costly[r][x] & !stepped[r][x])));
 
-    for piece in &moved_and_soniced {
-        generated.insert(Placement::new(board, piece));
+    // where the last move can be a rotation, by whether it was a 180 and the kick it used. `unspun`
+    // gets the spawn and rotations the piece fell out of, which lock like any other move.
+    let mut kicked = [[[[0 as Column; W]; 4]; MAX_KICKS]; 2];
+    let mut unspun = [[0 as Column; W]; 4];
+    unspun[0][spawn_x] = settle(1 << corners[0].0, free[0][spawn_x], gravity);
+    for rotation @ &Rotation { from, to, half_turn, .. } in rotations {
+        let (resting, free) = (&resting[to], &free[to]);
+        let kicked = &mut kicked[half_turn as usize];
+        // unsettled, so the resting positions keep their kick
+        turn(&movable[from], rotation, free, Gravity::Zero, |kick, x, turned| {
+            kicked[kick][to][x] |= turned & resting[x];
+            // at 20G the piece falls out of the spin
+            unspun[to][x] |= settle(turned & !resting[x], free[x], gravity);
+        });
     }
-    for spin in [RotateCcw, RotateCw] {
-        for piece in &moved_and_soniced {
-            if let Some(spun) = board.try_command(piece, spin) {
-                generated.insert(Placement::new(board, &board.force_sonic_drop(&spun)));
+    // where the last move can be a drop or a shift
+    let mut unkicked = [[0 as Column; W]; 4];
+    for rotation in 0..4 {
+        let (movable, free) = (&movable[rotation], &free[rotation]);
+        let mut slid_in = [0; W];
+        if rules.lock_reset_limit.is_some() {
+            for dx in [-1, 1] {
+                for (slid_in, end) in slid_in.iter_mut().zip(slide(movable, free, gravity, dx)) {
+                    *slid_in |= end;
+                }
+            }
+        }
+        for x in 0..W {
+            let mut shifted_in = slid_in[x];
+            if x > 0 {
+                shifted_in |= settle(movable[x - 1] & free[x], free[x], gravity);
+            }
+            if x + 1 < W {
+                shifted_in |= settle(movable[x + 1] & free[x], free[x], gravity);
+            }
+            let dropped_in = reached[rotation][x] >> 1;
+            let positions = reached[rotation][x] & (unspun[rotation][x] | shifted_in | dropped_in);
+            unkicked[rotation][x] = positions & resting[rotation][x];
+        }
+    }
+    // when the kick doesn't change the spin, every way into a position locks the same
+    if rules.spin_rule == SpinRule::Immobile {
+        for kicked in kicked.iter_mut().flatten() {
+            for (unkicked, kicked) in unkicked.iter_mut().zip(kicked) {
+                for (unkicked, kicked) in unkicked.iter_mut().zip(kicked) {
+                    *unkicked |= std::mem::take(kicked);
+                }
             }
         }
     }
 
+    // blocked above, to the left and to the right
+    let immobile: [[Column; W]; 4] = array::from_fn(|rotation| {
+        let free = &free[rotation];
+        array::from_fn(|x| {
+            let left = match x {
+                0 => 0,
+                _ => free[x - 1],
+            };
+            let right = free.get(x + 1).copied().unwrap_or(0);
+            !(free[x] >> 1 | left | right)
+        })
+    });
+    let mut generated = AHashSet::with_capacity(64);
+    let mut lock = |rotation: usize, x: usize, mut positions: Column, last_kick: Option<Kick>| {
+        while positions != 0 {
+            let y = positions.trailing_zeros() as i8;
+            positions &= positions - 1;
+            let mut falling_piece = rotated[rotation];
+            falling_piece.coords = shapes[rotation].cells.map(|(dy, dx)| (y + dy, x as i8 + dx));
+            let is_immobile = || immobile[rotation][x] >> y & 1 == 1;
+            let spin = spin::classify_with(board, &falling_piece, last_kick, is_immobile, rules);
+            generated.insert(Placement { piece_location: falling_piece.coords, spin });
+        }
+    };
+    for rotation in 0..4 {
+        for x in 0..W {
+            lock(rotation, x, unkicked[rotation][x], None);
+            for (half_turn, kicked) in kicked.iter().enumerate() {
+                for (index, kicked) in kicked.iter().enumerate() {
+                    let kick = Kick { index, half_turn: half_turn == 1 };
+                    lock(rotation, x, kicked[rotation][x], Some(kick));
+                }
+            }
+        }
+    }
     generated
 }
 
 #[cfg(test)]
 mod test {
-    use super::{move_gen, move_gen_with_action};
-    use crate::{botris::types::Piece, tetris_core::engine::BitBoard};
+    use ahash::AHashSet;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{
+        move_gen, move_gen_reference, move_gen_snapshot, move_gen_with_action, move_gen_with_path,
+        Placement,
+    };
+    use crate::{
+        botris::types::{ClearName, Command, Piece},
+        tetris_core::{
+            engine::{BitBoard, EMPTY_BOARD},
+            piece::{FallingPiece, PieceCoords},
+            rotation::{NoKicks, PIECES},
+            ruleset::{Gravity, Ruleset},
+            snapshot::GameSnapshot,
+            spin::Spin,
+        },
+        validation::{replay_action, validate_action, ActionError},
+    };
 
     #[test]
     fn test_move_gen() {
@@ -131,8 +700,8 @@ mod test {
             "[]  []  [][]    []  ",
             "[]    []            ",
         ]);
-        let moves = move_gen(&board, Piece::L);
-        let moves_with_action = move_gen_with_action(&board, Piece::L);
+        let moves = move_gen(&board, Piece::L, &Ruleset::BOTRIS);
+        let moves_with_action = move_gen_with_action(&board, Piece::L, &Ruleset::BOTRIS);
         BitBoard::print_rows(
             &moves
                 .iter()
@@ -143,4 +712,269 @@ mod test {
         assert_eq!(moves.len(), moves_with_action.len());
         println!("generated {}", moves_with_action.len());
     }
+
+    #[test]
+    fn test_finesse() {
+        let board = BitBoard::from_strs(&["[]            [][][]"]);
+        let rules = Ruleset::BOTRIS;
+        let moves = move_gen_with_action(&board, Piece::T, &rules);
+        assert_eq!(moves, move_gen_with_action(&board, Piece::T, &rules));
+        for (placement, action) in &moves {
+            check_action(&board, Piece::T, placement, action, &rules);
+        }
+        let action = |mut cells: PieceCoords| {
+            cells.sort();
+            let (_, action) =
+                moves.iter().find(|(placement, _)| placement.piece_location == cells).unwrap();
+            action.clone()
+        };
+        use Command::*;
+        assert_eq!(action([(0, 3), (0, 4), (0, 5), (1, 4)]), []);
+        assert_eq!(action([(1, 0), (1, 1), (1, 2), (2, 1)]), [SonicLeft]);
+        assert_eq!(action([(0, 1), (0, 2), (0, 3), (1, 2)]), [MoveLeft, MoveLeft]);
+        assert_eq!(action([(0, 1), (1, 0), (1, 1), (2, 1)]), [SonicLeft, RotateCcw]);
+        assert_eq!(action([(1, 9), (2, 8), (2, 9), (3, 9)]), [RotateCcw, SonicRight]);
+    }
+
+    #[test]
+    fn test_symmetric_pieces() {
+        // each way to fill the same cells is one placement, with the shorter action
+        let moves = move_gen_with_action(&EMPTY_BOARD, Piece::I, &Ruleset::BOTRIS);
+        assert_eq!(moves.len(), 7 + 10);
+        assert_eq!(
+            moves
+                [&Placement { piece_location: [(0, 4), (1, 4), (2, 4), (3, 4)], spin: Spin::None }],
+            [Command::RotateCcw]
+        );
+        for (piece, count) in [(Piece::O, 9), (Piece::S, 8 + 9), (Piece::Z, 8 + 9)] {
+            assert_eq!(move_gen(&EMPTY_BOARD, piece, &Ruleset::BOTRIS).len(), count);
+        }
+    }
+
+    #[test]
+    fn test_move_gen_snapshot() {
+        let rules = Ruleset::BOTRIS;
+        let mut snapshot = GameSnapshot {
+            falling_piece: FallingPiece::new(Piece::T),
+            held: Piece::I,
+            can_hold: true,
+            ..Default::default()
+        };
+        let moves = move_gen_snapshot(&snapshot, &rules);
+        assert_eq!(moves.len(), 34 + 17);
+        for held_move in moves.iter().filter(|m| m.piece == Piece::I) {
+            assert_eq!(held_move.action[0], Command::Hold);
+            assert_eq!(held_move.held, Piece::T);
+        }
+
+        snapshot.can_hold = false;
+        assert!(move_gen_snapshot(&snapshot, &rules).iter().all(|m| m.piece == Piece::T));
+        snapshot.can_hold = true;
+        snapshot.held = Piece::T;
+        assert_eq!(move_gen_snapshot(&snapshot, &rules).len(), 34);
+    }
+
+    #[test]
+    fn test_180_spin() {
+        let board = BitBoard::from_strs(&[
+            "      []  [][]    []",
+            "  [][][]  []      []",
+            "  []  []  []    [][]",
+            "  [][][]  []        ",
+        ]);
+        let without_180 = Ruleset { allow_180: false, ..Ruleset::TETRIO };
+        let moves = move_gen(&board, Piece::T, &Ruleset::TETRIO);
+        let moves_with_action = move_gen_with_action(&board, Piece::T, &Ruleset::TETRIO);
+        let only_180: Vec<_> =
+            moves.difference(&move_gen(&board, Piece::T, &without_180)).copied().collect();
+        BitBoard::print_rows(
+            &only_180
+                .iter()
+                .map(|placement| (&board, Some(placement.piece_location)))
+                .collect::<Vec<_>>(),
+            5,
+        );
+        let spin = only_180
+            .iter()
+            .find(|placement| placement.piece_location == [(0, 6), (1, 6), (1, 7), (2, 6)])
+            .unwrap();
+        // only two corners are filled, so TETR.IO calls it a mini
+        assert_eq!(spin.spin, Spin::AllMini);
+        assert!(moves_with_action[spin].contains(&Command::Rotate180));
+        assert!(move_gen_with_action(&board, Piece::T, &without_180)
+            .values()
+            .all(|action| !action.contains(&Command::Rotate180)));
+    }
+
+    /// `action` puts `piece` on `placement` when replayed from spawn
+    fn check_action(
+        board: &BitBoard,
+        piece: Piece,
+        placement: &Placement,
+        action: &[Command],
+        rules: &Ruleset,
+    ) {
+        let snapshot = GameSnapshot {
+            matrix: *board,
+            falling_piece: FallingPiece::new(piece),
+            ..Default::default()
+        };
+        assert_eq!(validate_action(&snapshot, action, placement, rules), Ok(()));
+    }
+
+    /// finds the T spin covering `cells`, checks its action leads there and returns the clear
+    fn t_spin(board: &BitBoard, cells: PieceCoords) -> Option<ClearName> {
+        let rules = Ruleset::BOTRIS;
+        let (placement, action) = move_gen_with_action(board, Piece::T, &rules)
+            .into_iter()
+            .find(|(placement, _)| {
+                placement.piece_location == cells && placement.spin != Spin::None
+            })
+            .unwrap();
+        assert_eq!(placement.spin, Spin::AllSpin);
+        check_action(board, Piece::T, &placement, &action, &rules);
+        let (_board, _data, info) =
+            board.lock_piece(&cells, placement.spin, Default::default(), &rules, None).unwrap();
+        info.clear_name
+    }
+
+    #[test]
+    fn test_tst() {
+        // needs two spins after the drop
+        let board = BitBoard::from_strs(&[
+            "    []    [][][]    ",
+            "              [][]  ",
+            "[][][][][]  [][][][]",
+            "[][][][]    [][][][]",
+            "[][][][][]  [][][][]",
+        ]);
+        assert_eq!(t_spin(&board, [(0, 5), (1, 4), (1, 5), (2, 5)]), Some(ClearName::AST));
+    }
+
+    #[test]
+    fn test_stsd() {
+        // the T slides under the overhang, then spins down twice
+        let board = BitBoard::from_strs(&[
+            "      []      []    ",
+            "  []  []      []    ",
+            "      []            ",
+            "[][][]  [][][]  [][]",
+            "[][][][][][][]    []",
+            "[][][][][][][]  [][]",
+        ]);
+        assert_eq!(t_spin(&board, [(0, 7), (1, 7), (1, 8), (2, 7)]), Some(ClearName::ASD));
+    }
+
+    #[test]
+    fn test_matches_reference() {
+        let no_kicks = Ruleset { rotation_system: &NoKicks, ..Ruleset::GUIDELINE };
+        let twenty_g = Ruleset { gravity: Gravity::Instant, ..Ruleset::TETRIO };
+        let few_resets = Ruleset { lock_reset_limit: Some(2), ..Ruleset::TETRIO };
+        let one_reset = Ruleset { lock_reset_limit: Some(1), ..twenty_g };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            // a few solid rows under a messy top
+            let height = rng.gen_range(2..12);
+            let rows: Vec<String> = (0..height)
+                .map(|row| {
+                    let density = if row < 3 { 0.3 } else { 0.8 };
+                    (0..10).map(|_| if rng.gen_bool(density) { "[]" } else { "  " }).collect()
+                })
+                .collect();
+            let board = BitBoard::from_strs(&rows.iter().map(String::as_str).collect::<Vec<_>>());
+            for rules in
+                [Ruleset::BOTRIS, Ruleset::TETRIO, no_kicks, twenty_g, few_resets, one_reset]
+            {
+                for piece in PIECES {
+                    let expected = move_gen_reference(&board, piece, &rules);
+                    if move_gen(&board, piece, &rules) != expected {
+                        board.print_board(None);
+                        panic!("{piece:?} placements differ from the reference with {rules:?}");
+                    }
+                    // the path search stops early, but not before every placement has a path
+                    assert_eq!(move_gen_with_path(&board, piece, &rules).len(), expected.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_twenty_g() {
+        // the piece lands in the well on spawn and can't climb out
+        let board = BitBoard::from_strs(&["    []        []    "; 15]);
+        let rules = Ruleset { gravity: Gravity::Instant, ..Ruleset::BOTRIS };
+        let moves = move_gen_with_action(&board, Piece::T, &rules);
+        assert!(!moves.is_empty());
+        for (placement, action) in &moves {
+            assert!(placement.piece_location.iter().all(|&(_, x)| (3..7).contains(&x)));
+            check_action(&board, Piece::T, placement, action, &rules);
+        }
+        assert_eq!(move_gen(&board, Piece::T, &rules), moves.into_keys().collect());
+        assert!(move_gen(&board, Piece::T, &Ruleset::BOTRIS)
+            .iter()
+            .any(|placement| placement.piece_location.iter().any(|&(_, x)| x < 2)));
+    }
+
+    #[test]
+    fn test_soft_drop() {
+        // the only way in is to stop under the roof on the way down
+        let board = BitBoard::from_strs(&[
+            "[][][][][][]        ",
+            "                    ",
+            "                    ",
+            "[][][][][][]        ",
+        ]);
+        let rules = Ruleset::TETRIO;
+        let paths = move_gen_with_path(&board, Piece::O, &rules);
+        let (placement, path) = paths
+            .iter()
+            .find(|(placement, _)| placement.piece_location == [(1, 0), (1, 1), (2, 0), (2, 1)])
+            .unwrap();
+        assert!(path.commands.contains(&Command::Drop));
+        assert_eq!(path.commands.last(), Some(&Command::SonicLeft));
+        // sliding off the roof after sonic dropping onto it
+        assert_eq!(path.lock_resets, 1);
+        check_action(&board, Piece::O, placement, &path.commands, &rules);
+    }
+
+    #[test]
+    fn test_lock_resets() {
+        // at 20G every move restarts lock delay
+        let rules = Ruleset { gravity: Gravity::Instant, ..Ruleset::TETRIO };
+        let paths = move_gen_with_path(&EMPTY_BOARD, Piece::T, &rules);
+        assert!(paths.values().all(|path| path.lock_resets == path.commands.len() as u32));
+        let flat_left = paths
+            .iter()
+            .find(|(placement, _)| placement.piece_location == [(0, 0), (0, 1), (0, 2), (1, 1)])
+            .unwrap();
+        assert_eq!(flat_left.1.commands, [Command::SonicLeft]);
+        assert_eq!(flat_left.1.lock_resets, 1);
+
+        let no_resets = Ruleset { lock_reset_limit: Some(0), ..rules };
+        assert_eq!(move_gen_with_path(&EMPTY_BOARD, Piece::T, &no_resets).len(), 1);
+        let one_reset = Ruleset { lock_reset_limit: Some(1), ..rules };
+        let paths = move_gen_with_path(&EMPTY_BOARD, Piece::T, &one_reset);
+        assert!(paths.values().all(|path| path.lock_resets <= 1));
+        // the search plans with the same placements the actions can reach
+        let placements: AHashSet<_> = paths.keys().copied().collect();
+        assert_eq!(move_gen(&EMPTY_BOARD, Piece::T, &one_reset), placements);
+        assert!(placements.len() < move_gen(&EMPTY_BOARD, Piece::T, &rules).len());
+        assert_eq!(move_gen(&EMPTY_BOARD, Piece::T, &no_resets).len(), 1);
+        let snapshot =
+            GameSnapshot { falling_piece: FallingPiece::new(Piece::T), ..Default::default() };
+        let two_moves = [Command::MoveLeft, Command::MoveLeft];
+        assert_eq!(
+            replay_action(&snapshot, &two_moves, &one_reset),
+            Err(ActionError::LockedOut { index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_narrow_board() {
+        let board = BitBoard::<4>::from_rows(&["    []  ", "[]    []"]);
+        let rules = Ruleset { visible_height: 6, ..Ruleset::BOTRIS };
+        for piece in PIECES {
+            assert_eq!(move_gen(&board, piece, &rules), move_gen_reference(&board, piece, &rules));
+        }
+    }
 }
@@ -0,0 +1,63 @@
+//! What every move picking strategy has in common, so they can be swapped out and played against each other.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    botris::types::Command,
+    tetris_core::{ruleset::Ruleset, snapshot::GameSnapshot},
+};
+
+pub trait Search {
+    /// the action to play from `genesis`
+    fn suggest_action(&mut self, genesis: &GameSnapshot, rules: &Ruleset) -> Vec<Command>;
+    /// how the last `suggest_action` went
+    fn last_search(&self) -> SearchStats;
+}
+
+/// What a strategy answers when the piece fits nowhere. Nothing it does can help, so it wiggles.
+pub const DEATH_WIGGLE: [Command; 4] =
+    [Command::SonicLeft, Command::SonicRight, Command::SonicLeft, Command::SonicRight];
+
+/// How long a search may think before answering with the best move found so far.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum TimeBudget {
+    /// search as deep as the strategy goes however long it takes
+    #[default]
+    Unlimited,
+    PerMove(Duration),
+    /// think just long enough to keep up this many pieces per second
+    Pps(f32),
+}
+
+impl TimeBudget {
+    /// when a search started at `start` has to stop. None if it never does.
+    pub fn deadline(self, start: Instant) -> Option<Instant> {
+        match self {
+            TimeBudget::Unlimited => None,
+            TimeBudget::PerMove(duration) => Some(start + duration),
+            TimeBudget::Pps(pps) => {
+                Duration::try_from_secs_f32(1.0 / pps).ok().map(|time| start + time)
+            }
+        }
+    }
+}
+
+/// How far a search got before it answered.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SearchStats {
+    /// how many pieces past the current one the search looked
+    pub depth: usize,
+    /// nodes evaluated, counting the ones that didn't make it into the suggestion
+    pub nodes: usize,
+    /// nodes carried over from the last search instead of generated again
+    pub reused: usize,
+    /// nodes dropped for reaching the same state as a better one at the same depth
+    pub transpositions: usize,
+    /// guesses at the pieces past the visible queue that the beam search averaged over
+    pub samples: usize,
+    /// rounds of tree search run, each down its own guess at the pieces past the visible queue
+    pub iterations: usize,
+    pub elapsed: Duration,
+    /// the deadline passed before the search was done
+    pub timed_out: bool,
+}
@@ -1,4 +1,10 @@
-use std::{f32, rc::Rc};
+use std::{
+    f32,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
 
 use ordered_float::OrderedFloat;
 
@@ -6,7 +12,10 @@ use super::evaluation::Evaluate;
 use crate::{
     botris::types::Piece,
     movegen::Placement,
-    tetris_core::engine::{BitBoard, BoardData},
+    tetris_core::{
+        engine::{BitBoard, BoardData, ClearError, ClearInfo},
+        ruleset::Ruleset,
+    },
 };
 
 pub struct EvaledPlacementNode {
@@ -14,47 +23,89 @@ pub struct EvaledPlacementNode {
     pub placement: Placement,
     pub board_after_clears: BitBoard,
     pub board_data: BoardData,
+    pub clear: ClearInfo,
     pub held: Piece, // not sure where this should belong yet.
     // pub cumm_attack: u32, // not sure where this should belong yet.
-    pub parent: Option<Rc<EvaledPlacementNode>>,
+    pub parent: Option<Arc<EvaledPlacementNode>>,
     pub score: OrderedFloat<f32>,
     pub depth: usize,
+    id: usize,
 }
 
+/// the id of the next node made, so no two nodes ever share one
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
 impl EvaledPlacementNode {
+    /// `next_piece` is the piece that spawns after this one, if it's known
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         board: &BitBoard,
         placement: Placement,
         held: Piece,
-        parent: Option<Rc<EvaledPlacementNode>>,
+        parent: Option<Arc<EvaledPlacementNode>>,
         board_data_if_root: Option<BoardData>,
         evaluator: &impl Evaluate,
-    ) -> Rc<Self> {
-        // calculate lines and clear data
-        let mut filled_board = *board;
-        for (y, x) in placement.piece_location {
-            filled_board.set(y as usize, x as usize, true);
-        }
+        rules: &Ruleset,
+        next_piece: Option<Piece>,
+    ) -> Result<Arc<Self>, ClearError> {
         let data = if let Some(parent) = &parent {
             parent.board_data
         } else {
             board_data_if_root.unwrap()
         };
-        let (board_after_clears, board_data) = filled_board.hard_drop(placement.all_spin, data);
+        // calculate lines and clear data
+        let (board_after_clears, board_data, clear) =
+            board.lock_piece(&placement.piece_location, placement.spin, data, rules, next_piece)?;
+        let score = match clear.top_out {
+            Some(_) => OrderedFloat(f32::NEG_INFINITY),
+            None => evaluator.eval(&board_after_clears, &board_data, false),
+        };
 
-        Rc::new(EvaledPlacementNode {
-            score: evaluator.eval(&board_after_clears, &board_data, false),
+        Ok(Arc::new(EvaledPlacementNode {
+            score,
             parent: parent.clone(),
             placement,
             held,
             board: *board,
             board_after_clears,
             board_data,
+            clear,
             depth: parent.as_ref().map_or(0, |p| p.depth + 1),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }))
+    }
+
+    /// A copy of this node under `parent` of a new search whose genesis is where `base` left off.
+    /// Attack and garbage are counted from `base` again, so the node is rescored.
+    pub fn rebased(
+        &self,
+        parent: Arc<EvaledPlacementNode>,
+        base: &BoardData,
+        evaluator: &impl Evaluate,
+    ) -> Arc<Self> {
+        let mut board_data = self.board_data;
+        board_data.cummulative_attack -= base.cummulative_attack;
+        board_data.simulated_garbage -= base.simulated_garbage;
+        let score = match self.clear.top_out {
+            Some(_) => OrderedFloat(f32::NEG_INFINITY),
+            None => evaluator.eval(&self.board_after_clears, &board_data, false),
+        };
+        Arc::new(EvaledPlacementNode {
+            score,
+            depth: parent.depth + 1,
+            parent: Some(parent),
+            board_data,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            ..*self
         })
     }
 
-    pub fn get_root(self: &Rc<Self>) -> Rc<EvaledPlacementNode> {
+    /// tells nodes apart, even ones that have been dropped
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn get_root(self: &Arc<Self>) -> Arc<EvaledPlacementNode> {
         self.parent.as_ref().map_or(self.clone(), |p| p.get_root())
     }
 
@@ -73,7 +124,7 @@ impl EvaledPlacementNode {
         path
     }
 
-    pub fn get_nodes_from_root(self: &Rc<Self>) -> Vec<Rc<EvaledPlacementNode>> {
+    pub fn get_nodes_from_root(self: &Arc<Self>) -> Vec<Arc<EvaledPlacementNode>> {
         let mut list = vec![self.clone()];
         let mut current = self.parent.clone();
         while let Some(node) = current {
@@ -108,9 +159,9 @@ impl Eq for EvaledPlacementNode {}
 /// print each placement in a single row
 pub fn print_nodes<'a, I>(nodes: I, chunk_size: usize)
 where
-    I: IntoIterator<Item = &'a Rc<EvaledPlacementNode>>,
+    I: IntoIterator<Item = &'a Arc<EvaledPlacementNode>>,
 {
-    let nodes: Vec<&Rc<EvaledPlacementNode>> = nodes.into_iter().collect();
+    let nodes: Vec<&Arc<EvaledPlacementNode>> = nodes.into_iter().collect();
     for chunk in nodes.chunks(chunk_size) {
         BitBoard::print_rows(
             &chunk
@@ -1,31 +1,109 @@
-use super::piece::{FallingPiece, PieceCoords};
-use crate::botris::{self, types::Command};
+use std::{error::Error, fmt::Display};
+
+use super::{
+    garbage::GarbageHoles,
+    piece::{FallingPiece, PieceCoords},
+    rotation::Kick,
+    ruleset::{Gravity, Ruleset},
+    spin::Spin,
+};
+use crate::botris::{
+    self,
+    game_info::BOARD_WIDTH,
+    types::{ClearName, Command, Piece},
+};
 
 // index 0 is the bottom of the board
 // pub type Board = [[bool; 10]; BOARD_HEIGHT];
 // pub const EMPTY_BOARD: Board = [[false; 10]; BOARD_HEIGHT];
 
+/// one bit per row, bit 0 is the bottom
+pub type Column = u64;
+
+/// `W` columns wide. every board is `BITBOARD_HEIGHT` rows tall, whatever the game's visible height.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
-pub struct BitBoard {
-    pub cols: [u32; 10],
+pub struct BitBoard<const W: usize = BOARD_WIDTH> {
+    pub cols: [Column; W],
 }
-pub const BITBOARD_HEIGHT: usize = 32;
+pub const BITBOARD_HEIGHT: usize = Column::BITS as usize;
 
-pub const EMPTY_BOARD: BitBoard = BitBoard { cols: [0; 10] };
+pub const EMPTY_BOARD: BitBoard = BitBoard::EMPTY;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
 pub struct BoardData {
     pub b2b: bool,
+    /// clears in a row that continued b2b
+    pub b2b_chain: u32,
     pub incoming: [u32; 8],
     pub combo: u32,
     pub cummulative_attack: u32,
+    /// garbage lines that entered the board since the start of the simulation
     pub simulated_garbage: u32,
+    pub garbage_holes: GarbageHoles,
+}
+
+/// Everything that happened when a piece locked.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct ClearInfo {
+    /// None if no lines were cleared. a clear that empties the board is a `PC`.
+    pub clear_name: Option<ClearName>,
+    pub lines: u32,
+    pub combo: u32,
+    /// b2b is active after this placement
+    pub b2b: bool,
+    /// this clear continued b2b and got the bonus
+    pub b2b_continued: bool,
+    /// attack produced, before cancelling garbage
+    pub attack: u32,
+    pub attack_sent: u32,
+    pub garbage_cancelled: u32,
+    pub garbage_received: u32,
+    /// the game is lost after this placement
+    pub top_out: Option<TopOut>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TopOut {
+    /// the next piece overlaps the stack where it spawns
+    BlockOut,
+    /// a piece locked entirely above the visible height
+    LockOut,
+    /// garbage pushed the stack past `Ruleset::board_height`
+    GarbageOut,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClearError {
+    /// the ruleset has no attack for this clear, e.g. 5 lines or an all-spin quad
+    ImpossibleClear { lines: u32, spin: Spin },
+}
+
+impl Display for ClearError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClearError::ImpossibleClear { lines, spin } => {
+                write!(f, "impossible clear: {lines} lines ({spin:?})")
+            }
+        }
+    }
 }
 
+impl Error for ClearError {}
+
 impl BitBoard {
+    /// see `from_rows` for boards that aren't `BOARD_WIDTH` wide
     pub fn from_strs(strs: &[&str]) -> BitBoard {
-        debug_assert_eq!(strs[0].len(), 20);
-        let mut board = EMPTY_BOARD;
+        Self::from_rows(strs)
+    }
+}
+
+impl<const W: usize> BitBoard<W> {
+    pub const EMPTY: Self = BitBoard { cols: [0; W] };
+
+    /// top row first, 2 characters per cell. anything but a space is filled.
+    pub fn from_rows(strs: &[&str]) -> Self {
+        debug_assert_eq!(strs[0].len(), 2 * W);
+        let mut board = Self::EMPTY;
         for (y, row) in strs.iter().rev().enumerate() {
             for (x, cell) in row.chars().step_by(2).enumerate() {
                 board.set(y, x, cell != ' ');
@@ -65,7 +143,7 @@ impl BitBoard {
         tentative_piece.coords.iter().any(|&(y, x)| {
             x < 0
                 || y < 0
-                || x >= 10
+                || x >= W as i8
                 || y >= BITBOARD_HEIGHT as i8
                 || self.at(y as usize, x as usize)
         })
@@ -74,7 +152,8 @@ impl BitBoard {
         Self::print_rows(&[(self, piece)], 1);
     }
     pub fn print_rows(boards: &[(&Self, Option<PieceCoords>)], row_size: usize) {
-        println!("{}", ">~~~~~~~~~~~~~~~~~~~~<".repeat(std::cmp::min(boards.len(), row_size)));
+        let border = format!(">{}<", "~".repeat(2 * W));
+        println!("{}", border.repeat(std::cmp::min(boards.len(), row_size)));
         for chunk in boards.chunks(row_size) {
             let highest_row = chunk
                 .iter()
@@ -92,7 +171,7 @@ impl BitBoard {
             for row in (0..rows_to_print).rev() {
                 for (board, piece_coords) in chunk {
                     print!("\"");
-                    for col in 0..10 {
+                    for col in 0..W {
                         if piece_coords
                             .is_some_and(|coords| coords.contains(&(row as i8, col as i8)))
                         {
@@ -107,7 +186,7 @@ impl BitBoard {
                 }
                 println!();
             }
-            println!("{}", ">~~~~~~~~~~~~~~~~~~~~<".repeat(chunk.len()));
+            println!("{}", border.repeat(chunk.len()));
         }
     }
     /// returns None if the command is impossible or does nothing.
@@ -115,81 +194,163 @@ impl BitBoard {
         &self,
         falling_piece: &FallingPiece,
         command: Command,
+        rules: &Ruleset,
     ) -> Option<FallingPiece> {
+        self.try_command_with_kick(falling_piece, command, rules).map(|(moved, _)| moved)
+    }
+
+    /// `try_command`, and the kick used if the command was a rotation that the piece didn't fall out of
+    pub fn try_command_with_kick(
+        &self,
+        falling_piece: &FallingPiece,
+        command: Command,
+        rules: &Ruleset,
+    ) -> Option<(FallingPiece, Option<Kick>)> {
+        let (moved, kick) = self.move_piece(falling_piece, command, rules)?;
+        let fallen = self.apply_gravity(&moved, rules);
+        Some((fallen, kick.filter(|_| fallen == moved)))
+    }
+
+    /// `try_command` before gravity
+    fn move_piece(
+        &self,
+        falling_piece: &FallingPiece,
+        command: Command,
+        rules: &Ruleset,
+    ) -> Option<(FallingPiece, Option<Kick>)> {
         use Command::*;
         let mut tentative_piece = *falling_piece;
         match command {
             MoveLeft => tentative_piece.shift(0, -1),
             MoveRight => tentative_piece.shift(0, 1),
             Drop => tentative_piece.shift(-1, 0),
-            SonicDrop => {
-                let distance = tentative_piece
-                    .coords
-                    .iter()
-                    .map(|&(y, x)| {
-                        if y == 0 {
-                            0
-                        } else {
-                            (!self.cols[x as usize] << (BITBOARD_HEIGHT - y as usize))
-                                .leading_ones() as i8
-                        }
-                    })
-                    .min()
-                    .unwrap();
-                debug_assert!(distance >= 0);
-                if distance == 0 {
-                    return None;
-                } else {
-                    tentative_piece.shift(-distance, 0)
-                }
-            }
-            RotateCw | RotateCcw => {
-                tentative_piece.rotate(command);
+            SonicDrop => return self.sonic_drop(falling_piece).map(|dropped| (dropped, None)),
+            Rotate180 if !rules.allow_180 => return None,
+            RotateCw | RotateCcw | Rotate180 => {
+                let new_rotation = falling_piece.rotation_after(command);
+                let turns = &rules.rotation_system.turns()[falling_piece.piece as usize]
+                    [falling_piece.rotation as usize][new_rotation as usize];
 
                 // go through kicks in kicktable
-                for [x_kick, y_kick] in
-                    tentative_piece.piece.kicks(command)[tentative_piece.rotation as usize]
-                {
-                    let mut kicked_piece = tentative_piece;
-                    kicked_piece.shift(y_kick, x_kick);
+                for (index, offsets) in turns.as_slice().iter().enumerate() {
+                    let mut kicked_piece = *falling_piece;
+                    kicked_piece.turn(offsets, new_rotation);
                     if !self.collides(&kicked_piece) {
-                        return Some(kicked_piece);
+                        let kick = Kick { index, half_turn: command == Rotate180 };
+                        return Some((kicked_piece, Some(kick)));
                     }
                 }
                 return None;
             }
-            _ => panic!(),
+            SonicLeft | SonicRight => {
+                // one cell at a time, so at 20G the piece falls into wells on the way
+                let step = if command == SonicLeft { MoveLeft } else { MoveRight };
+                let mut moved = None;
+                while let Some(shifted) =
+                    self.try_command(moved.as_ref().unwrap_or(falling_piece), step, rules)
+                {
+                    moved = Some(shifted);
+                }
+                return moved.map(|moved| (moved, None));
+            }
+            // these don't move the piece. see `Game`
+            Hold | HardDrop => return None,
         }
 
         if self.collides(&tentative_piece) || tentative_piece == *falling_piece {
             None
         } else {
-            Some(tentative_piece)
+            Some((tentative_piece, None))
+        }
+    }
+    /// None if the piece is already resting on something
+    fn sonic_drop(&self, falling_piece: &FallingPiece) -> Option<FallingPiece> {
+        let mut dropped = *falling_piece;
+        let distance = dropped
+            .coords
+            .iter()
+            .map(|&(y, x)| {
+                if y == 0 {
+                    0
+                } else {
+                    (!self.cols[x as usize] << (BITBOARD_HEIGHT - y as usize)).leading_ones() as i8
+                }
+            })
+            .min()
+            .unwrap();
+        debug_assert!(distance >= 0);
+        if distance == 0 {
+            None
+        } else {
+            dropped.shift(-distance, 0);
+            Some(dropped)
         }
     }
     pub fn try_commands(
         &self,
         falling_piece: &FallingPiece,
         commands: &[Command],
+        rules: &Ruleset,
     ) -> Option<FallingPiece> {
-        commands
-            .iter()
-            .try_fold(*falling_piece, |falling, &command| self.try_command(&falling, command))
+        commands.iter().try_fold(*falling_piece, |falling, &command| {
+            self.try_command(&falling, command, rules)
+        })
     }
 
     pub fn force_sonic_drop(&self, falling_piece: &FallingPiece) -> FallingPiece {
-        self.try_command(falling_piece, Command::SonicDrop).unwrap_or(*falling_piece)
+        self.sonic_drop(falling_piece).unwrap_or(*falling_piece)
+    }
+
+    /// where the piece ends up after falling on its own
+    pub fn apply_gravity(&self, falling_piece: &FallingPiece, rules: &Ruleset) -> FallingPiece {
+        match rules.gravity {
+            Gravity::Zero => *falling_piece,
+            Gravity::Instant => self.force_sonic_drop(falling_piece),
+        }
     }
 
     /* lock */
 
-    pub fn hard_drop(&self, all_spin: bool, data: BoardData) -> (BitBoard, BoardData) {
+    /// Places `piece` and hard drops. If known, `next_piece` is checked for block out.
+    pub fn lock_piece(
+        &self,
+        piece: &PieceCoords,
+        spin: Spin,
+        data: BoardData,
+        rules: &Ruleset,
+        next_piece: Option<Piece>,
+    ) -> Result<(Self, BoardData, ClearInfo), ClearError> {
+        let mut filled_board = *self;
+        for &(y, x) in piece {
+            filled_board.set(y as usize, x as usize, true);
+        }
+        let (new_board, new_data, mut info) = filled_board.hard_drop(spin, data, rules)?;
+        if piece.iter().all(|&(y, _)| y as usize >= rules.visible_height) {
+            info.top_out = Some(TopOut::LockOut);
+        } else if info.top_out.is_none()
+            && next_piece.is_some_and(|next| new_board.blocks_out(next, rules))
+        {
+            info.top_out = Some(TopOut::BlockOut);
+        }
+        Ok((new_board, new_data, info))
+    }
+
+    pub fn blocks_out(&self, piece: Piece, rules: &Ruleset) -> bool {
+        self.collides(&FallingPiece::spawn(piece, W, rules.visible_height))
+    }
+
+    pub fn hard_drop(
+        &self,
+        spin: Spin,
+        data: BoardData,
+        rules: &Ruleset,
+    ) -> Result<(Self, BoardData, ClearInfo), ClearError> {
         let mut new_board = *self;
         let mut new_data = data;
+        let mut info = ClearInfo::default();
 
         let mut cleared_lines = 0;
-        let mut rows_to_clear = new_board.cols.iter().fold(u32::MAX, |acc, &col| acc & col);
-        rows_to_clear &= u32::MAX << data.simulated_garbage;
+        let mut rows_to_clear = new_board.cols.iter().fold(Column::MAX, |acc, &col| acc & col);
 
         while rows_to_clear != 0 {
             let row = rows_to_clear.trailing_zeros();
@@ -206,64 +367,96 @@ impl BitBoard {
 
         if cleared_lines > 0 {
             new_data.combo += 1;
-            new_data.b2b = all_spin || cleared_lines == 4;
-            let mut attack = 0;
-            use crate::botris::{game_info::*, types::ClearName::*};
-            if all_spin {
-                attack += match cleared_lines {
-                    1 => ASS.attack(),
-                    2 => ASD.attack(),
-                    3 => AST.attack(),
-                    _ => {
-                        dbg!(cleared_lines);
-                        self.print_board(None);
-                        panic!();
-                    }
-                };
-            } else {
-                attack += match cleared_lines {
-                    1 => Single.attack(),
-                    2 => Double.attack(),
-                    3 => Triple.attack(),
-                    4 => Quad.attack(),
-                    _ => panic!(),
-                };
-            }
-            attack += COMBO_TABLE[new_data.combo as usize] as u32;
-            if new_board.cols.iter().all(|&x| x == 0) {
-                attack += PC.attack();
-            }
-            if data.b2b && new_data.b2b {
-                attack += B2B_ATTACK;
+            new_data.b2b = spin != Spin::None || cleared_lines == 4;
+            new_data.b2b_chain = if data.b2b && new_data.b2b { data.b2b_chain + 1 } else { 0 };
+            let mut attack = rules
+                .clear_attack(cleared_lines, spin)
+                .ok_or(ClearError::ImpossibleClear { lines: cleared_lines as u32, spin })?;
+            attack += rules.combo_attack(new_data.combo);
+            let perfect_clear = new_board.cols.iter().all(|&x| x == 0);
+            if perfect_clear {
+                attack += rules.perfect_clear_bonus;
             }
+            attack += rules.b2b_attack(new_data.b2b_chain);
             new_data.cummulative_attack += attack;
-            for garb in &mut new_data.incoming {
-                if attack <= *garb {
-                    *garb -= attack;
-                    break;
-                } else {
-                    attack -= *garb;
-                    *garb = 0;
+
+            // Botris only has names for all spins, so every kind of spin goes by those
+            use ClearName::*;
+            info.clear_name = Some(match (perfect_clear, spin != Spin::None, cleared_lines) {
+                (true, ..) => PC,
+                (false, true, 1) => ASS,
+                (false, true, 2) => ASD,
+                (false, true, _) => AST,
+                (false, false, 1) => Single,
+                (false, false, 2) => Double,
+                (false, false, 3) => Triple,
+                (false, false, _) => Quad,
+            });
+            info.b2b_continued = new_data.b2b_chain > 0;
+            info.attack = attack;
+
+            if rules.garbage_cancel {
+                for garb in &mut new_data.incoming {
+                    if attack <= *garb {
+                        *garb -= attack;
+                        info.garbage_cancelled += attack;
+                        attack = 0;
+                        break;
+                    } else {
+                        attack -= *garb;
+                        info.garbage_cancelled += *garb;
+                        *garb = 0;
+                    }
+                }
+                // cancelled lines never arrive, so neither do their holes
+                for _ in 0..info.garbage_cancelled {
+                    new_data.garbage_holes.next_hole(W);
                 }
             }
-            new_data.incoming[0] += new_data.incoming[1];
+            info.attack_sent = attack;
         } else {
             new_data.combo = 0;
-            let new_garbage_lines = new_data.incoming[0] as usize;
-            if new_garbage_lines >= BITBOARD_HEIGHT {
-                new_board.cols = [u32::MAX; 10];
-            } else if new_garbage_lines > 0 {
-                for col in &mut new_board.cols {
-                    *col = (*col << new_garbage_lines) | ((1 << new_garbage_lines) - 1);
-                }
+            new_data.b2b_chain = 0;
+        }
+        if cleared_lines == 0 || !rules.clear_delays_garbage {
+            let height = new_board.stack_height();
+            info.garbage_received = new_board.add_garbage(&mut new_data);
+            if height + info.garbage_received as usize > rules.board_height {
+                info.top_out = Some(TopOut::GarbageOut);
             }
-            new_data.simulated_garbage += new_garbage_lines as u32;
-            new_data.incoming[0] = 0;
         }
-        // incoming[0] properly updated. now shift everything else over by 1
-        new_data.incoming.copy_within(2.., 1);
+        // whatever is left of incoming[0] stays due. everything else moves 1 placement closer
+        let still_due = new_data.incoming[0];
+        new_data.incoming.copy_within(1.., 0);
+        new_data.incoming[7] = 0;
+        new_data.incoming[0] += still_due;
+
+        info.lines = cleared_lines as u32;
+        info.combo = new_data.combo;
+        info.b2b = new_data.b2b;
+        Ok((new_board, new_data, info))
+    }
 
-        (new_board, new_data)
+    /// pushes the garbage due now (`incoming[0]`) into the bottom of the board,
+    /// with holes from `data.garbage_holes`. returns the number of lines added.
+    fn add_garbage(&mut self, data: &mut BoardData) -> u32 {
+        let new_garbage_lines = data.incoming[0] as usize;
+        if new_garbage_lines >= BITBOARD_HEIGHT {
+            self.cols = [Column::MAX; W];
+        } else if new_garbage_lines > 0 {
+            let mut garbage: [Column; W] = [(1 << new_garbage_lines) - 1; W];
+            // first line to arrive ends up on top
+            for line in (0..new_garbage_lines).rev() {
+                let hole = data.garbage_holes.next_hole(W);
+                garbage[hole] &= !(1 << line);
+            }
+            for (col, garbage) in self.cols.iter_mut().zip(garbage) {
+                *col = (*col << new_garbage_lines) | garbage;
+            }
+        }
+        data.simulated_garbage += new_garbage_lines as u32;
+        data.incoming[0] = 0;
+        new_garbage_lines as u32
     }
 }
 
@@ -281,9 +474,11 @@ pub fn to_board(board: &botris::types::Board) -> BitBoard {
 
 #[cfg(test)]
 mod test {
-    use crate::tetris_core::piece::FallingPiece;
-
-    use super::BitBoard;
+    use super::{BitBoard, BoardData, ClearError, ClearInfo, TopOut};
+    use crate::{
+        botris::types::{ClearName, Piece},
+        tetris_core::{garbage::GarbageHoles, piece::FallingPiece, ruleset::Ruleset, spin::Spin},
+    };
 
     #[test]
     fn test_sonic_drop() {
@@ -295,6 +490,62 @@ mod test {
         board.print_board(Some(dropped.coords));
     }
 
+    #[test]
+    fn test_garbage_holes() {
+        let board = BitBoard::from_strs(&["[][][][]    [][][][]"]);
+        let data = BoardData {
+            incoming: [3, 0, 0, 0, 0, 0, 0, 0],
+            garbage_holes: GarbageHoles::from_sequence(&[1, 1, 8]),
+            ..Default::default()
+        };
+        let (new_board, new_data, info) =
+            board.hard_drop(Spin::None, data, &Ruleset::BOTRIS).unwrap();
+        new_board.print_board(None);
+        assert_eq!(
+            new_board,
+            BitBoard::from_strs(&[
+                "[][][][]    [][][][]",
+                "[]  [][][][][][][][]",
+                "[]  [][][][][][][][]",
+                "[][][][][][][][]  []",
+            ])
+        );
+        assert_eq!(new_data.simulated_garbage, 3);
+        assert_eq!(info.garbage_received, 3);
+        assert_eq!(new_data.incoming, [0; 8]);
+    }
+
+    #[test]
+    fn test_tall_board() {
+        let mut rows = vec!["[][][][][][][][][]  "; 45];
+        rows.insert(0, "[]                  ");
+        let board = BitBoard::from_strs(&rows);
+        assert_eq!(board.stack_height(), 46);
+        assert_eq!(board.column_height(9), 0);
+
+        let data = BoardData { incoming: [10, 0, 0, 0, 0, 0, 0, 0], ..Default::default() };
+        let (new_board, _data, _info) =
+            board.hard_drop(Spin::None, data, &Ruleset::BOTRIS).unwrap();
+        assert_eq!(new_board.stack_height(), 56);
+        assert!(new_board.at(55, 0));
+    }
+
+    #[test]
+    fn test_narrow_board() {
+        let board = BitBoard::<4>::from_rows(&["[]  [][]", "[]  [][]"]);
+        let (new_board, _data, info) =
+            board.hard_drop(Spin::None, Default::default(), &Ruleset::BOTRIS).unwrap();
+        assert_eq!(info.lines, 0);
+        let mut filled = new_board;
+        filled.set(0, 1, true);
+        filled.set(1, 1, true);
+        let (new_board, _data, info) =
+            filled.hard_drop(Spin::None, Default::default(), &Ruleset::BOTRIS).unwrap();
+        new_board.print_board(None);
+        assert_eq!(info.lines, 2);
+        assert_eq!(new_board, BitBoard::<4>::EMPTY);
+    }
+
     #[test]
     fn test_hard_drop() {
         let board = BitBoard::from_strs(&[
@@ -307,7 +558,82 @@ mod test {
             "[][][]  [][][][][][]",
             "[][]    [][][][][][]",
         ]);
-        let (new_board, _data) = board.hard_drop(false, Default::default());
+        let (new_board, _data, _info) =
+            board.hard_drop(Spin::None, Default::default(), &Ruleset::BOTRIS).unwrap();
+        new_board.print_board(None);
+        let all_spin_quad = board.hard_drop(Spin::AllSpin, Default::default(), &Ruleset::BOTRIS);
+        assert_eq!(
+            all_spin_quad,
+            Err(ClearError::ImpossibleClear { lines: 4, spin: Spin::AllSpin })
+        );
+    }
+
+    #[test]
+    fn test_clear_info() {
+        let board = BitBoard::from_strs(&[
+            "  [][][][][][][][][]",
+            "[][][][][][][][][][]",
+            "[][][][][][][][][][]",
+            "[][][][][][][][][][]",
+            "[][][][][][][][][][]",
+        ]);
+        let data = BoardData {
+            b2b: true,
+            combo: 2,
+            incoming: [2, 0, 0, 3, 0, 0, 0, 0],
+            ..Default::default()
+        };
+        let (new_board, _data, info) = board.hard_drop(Spin::None, data, &Ruleset::BOTRIS).unwrap();
+        new_board.print_board(None);
+        let expected = ClearInfo {
+            clear_name: Some(ClearName::Quad),
+            lines: 4,
+            combo: 3,
+            b2b: true,
+            b2b_continued: true,
+            // quad + combo + b2b
+            attack: 4 + 1 + 1,
+            attack_sent: 1,
+            garbage_cancelled: 5,
+            garbage_received: 0,
+            top_out: None,
+        };
+        assert_eq!(info, expected);
+    }
+
+    #[test]
+    fn test_top_out() {
+        let rules = Ruleset::BOTRIS;
+        let vertical_i = [(0, 0), (1, 0), (2, 0), (3, 0)];
+
+        let tower = BitBoard::from_strs(&["        [][]        "; 20]);
+        let (_board, _data, info) = tower
+            .lock_piece(
+                &[(20, 4), (20, 5), (21, 4), (21, 5)],
+                Spin::None,
+                Default::default(),
+                &rules,
+                None,
+            )
+            .unwrap();
+        assert_eq!(info.top_out, Some(TopOut::LockOut));
+
+        let board = BitBoard::from_strs(&["    [][][][][][][][]"; 21]);
+        let (new_board, _data, info) =
+            board.lock_piece(&vertical_i, Spin::None, Default::default(), &rules, None).unwrap();
+        assert_eq!(info.top_out, None);
+        assert!(new_board.blocks_out(Piece::T, &rules));
+        let (_board, _data, info) = board
+            .lock_piece(&vertical_i, Spin::None, Default::default(), &rules, Some(Piece::T))
+            .unwrap();
+        assert_eq!(info.top_out, Some(TopOut::BlockOut));
+
+        let board = BitBoard::from_strs(&["    [][][][][][][][]"; 28]);
+        let data = BoardData { incoming: [3, 0, 0, 0, 0, 0, 0, 0], ..Default::default() };
+        let (new_board, _data, info) =
+            board.lock_piece(&vertical_i, Spin::None, data, &rules, None).unwrap();
         new_board.print_board(None);
+        assert_eq!(info.garbage_received, 3);
+        assert_eq!(info.top_out, Some(TopOut::GarbageOut));
     }
 }
@@ -0,0 +1,355 @@
+//! A local game that plays out `Command`s the way the Botris server does,
+//! so the bot can be run without a connection.
+
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{
+    engine::{BitBoard, BoardData, ClearInfo, TopOut, BITBOARD_HEIGHT, EMPTY_BOARD},
+    garbage::{GarbageHoles, MAX_KNOWN_HOLES},
+    piece::{FallingPiece, PieceCoords},
+    rotation::Kick,
+    ruleset::Ruleset,
+    snapshot::GameSnapshot,
+    spin::Spin,
+};
+use crate::{
+    botris::types::{Command, Piece},
+    movegen::Placement,
+};
+
+/// number of queue pieces the player can see
+pub const PREVIEW_SIZE: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Hold { held: Piece },
+    PieceLocked { piece: Piece, coords: PieceCoords, spin: Spin, clear: ClearInfo },
+    GameOver { reason: TopOut },
+}
+
+pub struct Game {
+    pub board: BitBoard,
+    pub data: BoardData,
+    pub falling_piece: FallingPiece,
+    pub queue: VecDeque<Piece>,
+    pub held: Option<Piece>,
+    pub can_hold: bool,
+    pub rules: Ruleset,
+    pub pieces_placed: u32,
+    pub dead: bool,
+    /// hole column of every queued garbage line, in arrival order
+    pub garbage_holes: VecDeque<u8>,
+    last_hole: u8,
+    /// bags dealt into `queue` so far
+    bags: usize,
+    /// the kick of the falling piece's last move, if it was a rotation
+    last_kick: Option<Kick>,
+    rng: StdRng,
+    garbage_rng: StdRng,
+}
+
+impl Game {
+    pub fn new(seed: u64, rules: Ruleset) -> Self {
+        let mut game = Game {
+            board: EMPTY_BOARD,
+            data: BoardData::default(),
+            falling_piece: FallingPiece::new(Piece::I),
+            queue: VecDeque::new(),
+            held: None,
+            can_hold: true,
+            rules,
+            pieces_placed: 0,
+            dead: false,
+            garbage_holes: VecDeque::new(),
+            last_hole: 0,
+            bags: 0,
+            last_kick: None,
+            rng: StdRng::seed_from_u64(seed),
+            // separate from the piece rng so garbage doesn't change the queue
+            garbage_rng: StdRng::seed_from_u64(!seed),
+        };
+        game.spawn_next();
+        game
+    }
+
+    /// what the bot gets to see. None until something has been held, since search expects a hold piece.
+    pub fn snapshot(&self) -> Option<GameSnapshot> {
+        let queue: Vec<Piece> = self.queue.iter().take(PREVIEW_SIZE).copied().collect();
+        // the rest of the bag the last visible piece is from
+        let dealt = self.bags * 7 - self.queue.len() + queue.len();
+        let mut bag: Vec<Piece> =
+            self.queue.iter().skip(queue.len()).take((7 - dealt % 7) % 7).copied().collect();
+        bag.sort_unstable();
+        Some(GameSnapshot {
+            matrix: self.board,
+            falling_piece: self.falling_piece,
+            queue,
+            bag,
+            held: self.held?,
+            can_hold: self.can_hold,
+            combo: self.data.combo,
+            b2b: self.data.b2b,
+            incoming_garbage: self.data.incoming,
+            // holes aren't visible before they arrive
+            garbage_holes: GarbageHoles::Clean(self.last_hole),
+        })
+    }
+
+    /// queues garbage that arrives after `delay` more placements (0 = on the next one).
+    /// all lines share one random hole.
+    pub fn receive_garbage(&mut self, lines: u32, delay: usize) {
+        let hole = self.garbage_rng.gen_range(0..self.board.cols.len() as u8);
+        self.receive_garbage_with_holes(&vec![hole; lines as usize], delay);
+    }
+
+    /// holes should be received in the order they arrive, so delays shouldn't decrease between calls
+    pub fn receive_garbage_with_holes(&mut self, holes: &[u8], delay: usize) {
+        let delay = std::cmp::min(delay, self.data.incoming.len() - 1);
+        self.data.incoming[delay] += holes.len() as u32;
+        self.garbage_holes.extend(holes);
+    }
+
+    /// Plays one action: every command up to `HardDrop` (or the end), then the piece is hard dropped.
+    /// Commands that can't be performed are skipped, like the server does.
+    pub fn apply(&mut self, commands: &[Command]) -> Vec<Event> {
+        let mut events = vec![];
+        if self.dead {
+            return events;
+        }
+        for &command in commands {
+            match command {
+                Command::Hold => {
+                    if self.hold() {
+                        events.push(Event::Hold { held: self.held.unwrap() });
+                    }
+                }
+                Command::HardDrop => break,
+                _ => {
+                    if let Some(moved) =
+                        self.board.try_command_with_kick(&self.falling_piece, command, &self.rules)
+                    {
+                        (self.falling_piece, self.last_kick) = moved;
+                    }
+                }
+            }
+            if self.dead {
+                events.push(Event::GameOver { reason: TopOut::BlockOut });
+                return events;
+            }
+        }
+        self.lock(&mut events);
+        events
+    }
+
+    fn hold(&mut self) -> bool {
+        if !self.can_hold {
+            return false;
+        }
+        let current = self.falling_piece.piece;
+        match self.held.replace(current) {
+            Some(held) => self.spawn(held),
+            None => self.spawn_next(),
+        }
+        self.can_hold = false;
+        true
+    }
+
+    fn lock(&mut self, events: &mut Vec<Event>) {
+        let dropped = self.board.force_sonic_drop(&self.falling_piece);
+        // hard dropping any distance is a move too
+        let last_kick = self.last_kick.filter(|_| dropped == self.falling_piece);
+        let spin = Placement::new(&self.board, &dropped, last_kick, &self.rules).spin;
+
+        let known_holes: Vec<u8> =
+            self.garbage_holes.iter().take(MAX_KNOWN_HOLES).copied().collect();
+        self.data.garbage_holes = GarbageHoles::from_sequence(&known_holes);
+        let next_piece = self.queue.front().copied();
+        // the piece is on the board either way. score clears the ruleset has no name for as regular clears.
+        let (new_board, new_data, clear) = self
+            .board
+            .lock_piece(&dropped.coords, spin, self.data, &self.rules, next_piece)
+            .or_else(|_| {
+                self.board.lock_piece(
+                    &dropped.coords,
+                    Spin::None,
+                    self.data,
+                    &self.rules,
+                    next_piece,
+                )
+            })
+            .expect("a piece clears at most 4 lines");
+        events.push(Event::PieceLocked {
+            piece: dropped.piece,
+            coords: dropped.coords,
+            spin,
+            clear,
+        });
+
+        self.garbage_holes.drain(..clear.garbage_cancelled as usize);
+        let arrived: Vec<u8> =
+            self.garbage_holes.drain(..clear.garbage_received as usize).collect();
+        if let Some(&hole) = arrived.last() {
+            self.last_hole = hole;
+        }
+        let mut new_board = new_board;
+        // the board only knew the first `MAX_KNOWN_HOLES` holes and reused the last one after them,
+        // so the lines that arrived get their holes from the queue again
+        if (clear.garbage_cancelled + clear.garbage_received) as usize > MAX_KNOWN_HOLES {
+            // the first line to arrive is on top
+            for (row, &hole) in arrived.iter().rev().take(BITBOARD_HEIGHT).enumerate() {
+                for x in 0..new_board.cols.len() {
+                    new_board.set(row, x, x != hole as usize);
+                }
+            }
+        }
+
+        self.board = new_board;
+        self.data = new_data;
+        self.pieces_placed += 1;
+        self.can_hold = true;
+        if let Some(reason) = clear.top_out {
+            self.dead = true;
+            events.push(Event::GameOver { reason });
+            return;
+        }
+        self.spawn_next();
+        if self.dead {
+            events.push(Event::GameOver { reason: TopOut::BlockOut });
+        }
+    }
+
+    fn spawn_next(&mut self) {
+        while self.queue.len() <= PREVIEW_SIZE {
+            let mut bag = [Piece::I, Piece::J, Piece::L, Piece::O, Piece::S, Piece::T, Piece::Z];
+            bag.shuffle(&mut self.rng);
+            self.queue.extend(bag);
+            self.bags += 1;
+        }
+        let next = self.queue.pop_front().unwrap();
+        self.spawn(next);
+    }
+
+    fn spawn(&mut self, piece: Piece) {
+        self.last_kick = None;
+        self.falling_piece =
+            FallingPiece::spawn(piece, self.board.cols.len(), self.rules.visible_height);
+        if self.board.collides(&self.falling_piece) {
+            self.dead = true;
+        } else {
+            self.falling_piece = self.board.apply_gravity(&self.falling_piece, &self.rules);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, Game};
+    use crate::{
+        botris::types::{ClearName, Command::*, Piece},
+        tetris_core::{
+            engine::{BitBoard, TopOut},
+            piece::FallingPiece,
+            ruleset::Ruleset,
+        },
+    };
+
+    #[test]
+    fn test_seeded_queue() {
+        let game1 = Game::new(42, Ruleset::BOTRIS);
+        let game2 = Game::new(42, Ruleset::BOTRIS);
+        assert_eq!(game1.queue, game2.queue);
+        assert_eq!(game1.falling_piece, game2.falling_piece);
+        // first bag contains every piece once
+        let mut first_bag: Vec<Piece> = game1.queue.iter().take(6).copied().collect();
+        first_bag.push(game1.falling_piece.piece);
+        for piece in [Piece::I, Piece::J, Piece::L, Piece::O, Piece::S, Piece::T, Piece::Z] {
+            assert!(first_bag.contains(&piece));
+        }
+    }
+
+    #[test]
+    fn test_snapshot_bag() {
+        let mut game = Game::new(4, Ruleset::BOTRIS);
+        assert!(game.snapshot().is_none());
+        game.apply(&[Hold]);
+        let snapshot = game.snapshot().unwrap();
+        // the first bag is the held piece, the one that was dropped, the falling piece and 4 of the queue.
+        // the rest of the queue starts the second bag.
+        assert_eq!(snapshot.queue.len(), 6);
+        let mut second_bag = snapshot.bag.clone();
+        second_bag.extend(&snapshot.queue[4..]);
+        second_bag.sort_unstable();
+        assert_eq!(
+            second_bag,
+            [Piece::I, Piece::O, Piece::J, Piece::L, Piece::S, Piece::Z, Piece::T]
+        );
+    }
+
+    #[test]
+    fn test_play_quad() {
+        let mut game = Game::new(0, Ruleset::BOTRIS);
+        game.board = BitBoard::from_strs(&[
+            "[]                  ",
+            "[][][][][][][][][]  ",
+            "[][][][][][][][][]  ",
+            "[][][][][][][][][]  ",
+            "[][][][][][][][][]  ",
+        ]);
+        game.falling_piece = FallingPiece::new(Piece::I);
+        game.receive_garbage(3, 0);
+        let events = game.apply(&[RotateCw, SonicRight]);
+        game.board.print_board(None);
+        let Event::PieceLocked { clear, .. } = events[0] else { panic!() };
+        assert_eq!(clear.clear_name, Some(ClearName::Quad));
+        // 4 attack, 3 of it cancelled
+        assert_eq!(clear.attack_sent, 1);
+        assert!(game.garbage_holes.is_empty());
+        assert_eq!(game.board, BitBoard::from_strs(&["[]                  "]));
+        assert_eq!(game.pieces_placed, 1);
+    }
+
+    #[test]
+    fn test_many_garbage_lines() {
+        let mut game = Game::new(0, Ruleset::BOTRIS);
+        game.falling_piece = FallingPiece::new(Piece::I);
+        // more lines than the board is told the holes of
+        let holes: Vec<u8> = (0..18).map(|line| line % 10).collect();
+        game.receive_garbage_with_holes(&holes, 0);
+        let events = game.apply(&[SonicLeft]);
+        let Event::PieceLocked { clear, .. } = events[0] else { panic!() };
+        assert_eq!(clear.garbage_received, 18);
+        assert!(game.garbage_holes.is_empty());
+        for (line, &hole) in holes.iter().enumerate() {
+            let row = holes.len() - 1 - line;
+            for x in 0..10 {
+                assert_eq!(game.board.at(row, x), x != hole as usize, "line {line}");
+            }
+        }
+        // the piece is on top of all of it
+        assert!((0..4).all(|x| game.board.at(18, x)));
+    }
+
+    #[test]
+    fn test_top_out() {
+        let mut game = Game::new(7, Ruleset::BOTRIS);
+        let events = game.apply(&[Hold]);
+        assert!(matches!(events[0], Event::Hold { .. }));
+        let mut reason = None;
+        for _ in 0..100 {
+            for event in game.apply(&[]) {
+                if let Event::GameOver { reason: top_out } = event {
+                    reason = Some(top_out);
+                }
+            }
+            if game.dead {
+                break;
+            }
+        }
+        assert!(game.dead);
+        // pieces stack up in the middle until the next one can't spawn
+        assert_eq!(reason, Some(TopOut::BlockOut));
+        assert!(game.apply(&[SonicLeft]).is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+//! Hole columns of garbage lines that haven't reached the board yet.
+
+pub const MAX_KNOWN_HOLES: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GarbageHoles {
+    /// every line has its hole in the same column
+    Clean(u8),
+    /// each line gets a random hole drawn from this xorshift state
+    Messy(u32),
+    /// holes of the next `len` lines, in arrival order. lines after that reuse the last one.
+    Known { holes: [u8; MAX_KNOWN_HOLES], len: u8 },
+}
+
+impl Default for GarbageHoles {
+    fn default() -> Self {
+        GarbageHoles::Clean(0)
+    }
+}
+
+impl GarbageHoles {
+    pub fn messy(seed: u32) -> Self {
+        // xorshift gets stuck on 0
+        GarbageHoles::Messy(seed | 1)
+    }
+
+    /// only the first `MAX_KNOWN_HOLES` holes are kept
+    pub fn from_sequence(sequence: &[u8]) -> Self {
+        match sequence {
+            [] => GarbageHoles::default(),
+            [hole] => GarbageHoles::Clean(*hole),
+            _ => {
+                let len = std::cmp::min(sequence.len(), MAX_KNOWN_HOLES);
+                let mut holes = [0; MAX_KNOWN_HOLES];
+                holes[..len].copy_from_slice(&sequence[..len]);
+                GarbageHoles::Known { holes, len: len as u8 }
+            }
+        }
+    }
+
+    /// hole column of the next line to arrive, on a board `width` columns wide
+    pub fn next_hole(&mut self, width: usize) -> usize {
+        match self {
+            GarbageHoles::Clean(hole) => *hole as usize,
+            GarbageHoles::Messy(state) => {
+                *state ^= *state << 13;
+                *state ^= *state >> 17;
+                *state ^= *state << 5;
+                *state as usize % width
+            }
+            GarbageHoles::Known { holes, len } => {
+                let hole = holes[0];
+                if *len > 1 {
+                    holes.copy_within(1.., 0);
+                    *len -= 1;
+                }
+                hole as usize
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GarbageHoles;
+
+    #[test]
+    fn test_known_holes() {
+        let mut holes = GarbageHoles::from_sequence(&[3, 3, 7]);
+        let drawn: Vec<usize> = (0..5).map(|_| holes.next_hole(10)).collect();
+        assert_eq!(drawn, [3, 3, 7, 7, 7]);
+
+        let mut messy = GarbageHoles::messy(0);
+        assert!((0..100).map(|_| messy.next_hole(10)).all(|hole| hole < 10));
+    }
+}
@@ -1,3 +1,8 @@
 pub mod engine;
+pub mod game;
+pub mod garbage;
 pub mod piece;
+pub mod rotation;
+pub mod ruleset;
 pub mod snapshot;
+pub mod spin;
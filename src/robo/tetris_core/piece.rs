@@ -1,105 +1,12 @@
-//! Information about pieces, including initial spawn location/orientation, and all of their spins/kicks.
+//! Information about pieces, including initial spawn location/orientation.
 //! As a broad overview, the current piece is stored as indices of a 4x4 grid, along with its position in the game matrix.
+//! How pieces turn is up to a `RotationSystem`.
 
 use std::hash::Hash;
 
+use super::rotation::RotationSystem;
 use crate::botris::types::{Command, Piece};
 
-impl Piece {
-    fn rotations(&self) -> [PieceCoords; 4] {
-        match self {
-            Piece::I => [
-                [(2, 0), (2, 1), (2, 2), (2, 3)],
-                [(0, 2), (1, 2), (2, 2), (3, 2)],
-                [(1, 0), (1, 1), (1, 2), (1, 3)],
-                [(0, 1), (1, 1), (2, 1), (3, 1)],
-            ],
-            Piece::J => [
-                [(2, 0), (2, 1), (2, 2), (3, 0)],
-                [(1, 1), (2, 1), (3, 1), (3, 2)],
-                [(2, 0), (2, 1), (2, 2), (1, 2)],
-                [(1, 0), (1, 1), (2, 1), (3, 1)],
-            ],
-            Piece::L => [
-                [(2, 0), (2, 1), (2, 2), (3, 2)],
-                [(1, 1), (1, 2), (2, 1), (3, 1)],
-                [(1, 0), (2, 0), (2, 1), (2, 2)],
-                [(1, 1), (2, 1), (3, 0), (3, 1)],
-            ],
-            Piece::O => [[(2, 1), (2, 2), (3, 1), (3, 2)]; 4],
-            Piece::S => [
-                [(2, 0), (2, 1), (3, 1), (3, 2)],
-                [(1, 2), (2, 1), (2, 2), (3, 1)],
-                [(1, 0), (1, 1), (2, 1), (2, 2)],
-                [(1, 1), (2, 0), (2, 1), (3, 0)],
-            ],
-            Piece::T => [
-                [(2, 0), (2, 1), (2, 2), (3, 1)],
-                [(1, 1), (2, 1), (3, 1), (2, 2)],
-                [(2, 0), (1, 1), (2, 1), (2, 2)],
-                [(1, 1), (2, 0), (2, 1), (3, 1)],
-            ],
-            Piece::Z => [
-                [(2, 1), (2, 2), (3, 0), (3, 1)],
-                [(1, 1), (2, 1), (2, 2), (3, 2)],
-                [(1, 1), (1, 2), (2, 0), (2, 1)],
-                [(1, 0), (2, 0), (2, 1), (3, 1)],
-            ],
-        }
-    }
-
-    /// Returns [dx, dy] kicks to try into a target rotation.
-    pub fn kicks(&self, command: Command) -> [[[i8; 2]; 5]; 4] {
-        match self {
-            Piece::I => match command {
-                Command::RotateCcw => [
-                    [[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 1-0
-                    [[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 2-1
-                    [[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]], // 3-2
-                    [[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 0-3
-                ],
-                Command::RotateCw => [
-                    [[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 3-0
-                    [[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]], // 0-1
-                    [[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 1-2
-                    [[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 2-3
-                ],
-                _ => panic!(),
-            },
-            Piece::O => match command {
-                Command::RotateCcw => [
-                    [[-1, 1], [-2, 1], [-1, 2], [0, 1], [0, 0]],     // 1-0
-                    [[1, 1], [2, 1], [1, 2], [0, 1], [0, 0]],        // 2-1
-                    [[1, -1], [2, -1], [1, -2], [0, -1], [0, 0]],    // 3-2
-                    [[-1, -1], [-2, -1], [-1, -2], [0, -1], [0, 0]], // 0-3
-                ],
-                Command::RotateCw => [
-                    [[1, 1], [2, 1], [1, 2], [0, 1], [0, 0]],        // 3-0
-                    [[1, -1], [2, -1], [1, -2], [0, -1], [0, 0]],    // 0-1
-                    [[-1, -1], [-2, -1], [-1, -2], [0, -1], [0, 0]], // 1-2
-                    [[-1, 1], [-2, 1], [-1, 2], [0, 1], [0, 0]],     // 2-3
-                ],
-                _ => panic!(),
-            },
-            _ => match command {
-                Command::RotateCcw => [
-                    [[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],     // 1-0
-                    [[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]], // 2-1
-                    [[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],  // 3-2
-                    [[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],    // 0-3
-                ],
-                Command::RotateCw => [
-                    [[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],  // 3-0
-                    [[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]], // 0-1
-                    [[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],     // 1-2
-                    [[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],    // 2-3
-                ],
-                _ => panic!(),
-            },
-        }
-    }
-}
-
 /// absolute coordinates. signed to allow kick
 pub type PieceCoords = [(i8, i8); 4];
 
@@ -128,21 +35,35 @@ impl FallingPiece {
         }
     }
 
-    pub fn rotate(&mut self, rotation: Command) {
-        // TODO: precalculate all diffs
-        let new_rotation = (self.rotation
+    /// where `new` spawns, moved to the top middle of a board `width` wide with `visible_height` rows
+    pub fn spawn(piece: Piece, width: usize, visible_height: usize) -> Self {
+        let mut spawned = FallingPiece::new(piece);
+        spawned.shift(visible_height as i8 - 20, (width as i8 - 10) / 2);
+        spawned
+    }
+
+    /// turns the piece in place. kicks are up to the caller.
+    pub fn rotate(&mut self, rotation: Command, rotation_system: &dyn RotationSystem) {
+        let new_rotation = self.rotation_after(rotation);
+        self.turn(rotation_system.offsets(self.piece, self.rotation, new_rotation), new_rotation);
+    }
+
+    pub fn rotation_after(&self, rotation: Command) -> u8 {
+        (self.rotation
             + match rotation {
                 Command::RotateCcw => 3,
                 Command::RotateCw => 1,
+                Command::Rotate180 => 2,
                 _ => panic!(),
             })
-            % 4;
-        let rotations = self.piece.rotations();
-        let old_rel_coords = rotations[self.rotation as usize];
-        let new_rel_coords = rotations[new_rotation as usize];
-        for i in 0..4 {
-            self.coords[i].0 += new_rel_coords[i].0 - old_rel_coords[i].0;
-            self.coords[i].1 += new_rel_coords[i].1 - old_rel_coords[i].1;
+            % 4
+    }
+
+    /// moves each cell by its offset and sets the new rotation
+    pub fn turn(&mut self, offsets: &PieceCoords, new_rotation: u8) {
+        for (cell, (dy, dx)) in self.coords.iter_mut().zip(offsets) {
+            cell.0 += dy;
+            cell.1 += dx;
         }
         self.rotation = new_rotation;
     }
@@ -166,4 +87,4 @@ impl PartialEq for FallingPiece {
     fn eq(&self, other: &Self) -> bool {
         self.piece == other.piece && self.rotation == other.rotation && self.coords[0] == other.coords[0]
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,389 @@
+//! Rotation systems: how cells move when a piece turns and the kicks tried when a rotation collides.
+//! Kicks are `[dx, dy]` with y pointing up, in the order the game tries them.
+//! Everything is looked up in const tables indexed by `[piece][from][to]`, with pieces in `Piece` order.
+//! `BitBoard::try_command` only reads `turns`, which has every kick added to the offsets already.
+
+use std::fmt::Debug;
+
+use super::piece::PieceCoords;
+use crate::botris::types::Piece;
+
+pub trait RotationSystem: Debug + Send + Sync {
+    /// (dy, dx) of each cell when turning `piece` from rotation `from` to `to`.
+    /// defaults to the shapes in `srs_shapes`.
+    fn offsets(&self, piece: Piece, from: u8, to: u8) -> &'static PieceCoords {
+        &SRS_OFFSETS[piece as usize][from as usize][to as usize]
+    }
+
+    /// offsets to try when turning `piece` from rotation `from` to `to`. empty if the turn isn't possible.
+    fn kicks(&self, piece: Piece, from: u8, to: u8) -> &'static [[i8; 2]];
+
+    /// `offsets` plus each of `kicks`, for every turn
+    fn turns(&self) -> &'static TurnTables;
+}
+
+/// Guideline SRS. The O piece never kicks, and 180s only turn in place.
+#[derive(Debug, Clone, Copy)]
+pub struct Srs;
+
+/// SRS with the O piece kick table Botris uses.
+#[derive(Debug, Clone, Copy)]
+pub struct BotrisSrs;
+
+/// TETR.IO's SRS+: symmetric I kicks and 180 rotations.
+#[derive(Debug, Clone, Copy)]
+pub struct SrsPlus;
+
+/// SRS shapes, but a rotation only succeeds where the piece already is.
+#[derive(Debug, Clone, Copy)]
+pub struct NoKicks;
+
+/// every piece, in `Piece` order
+pub const PIECES: [Piece; 7] =
+    [Piece::I, Piece::O, Piece::J, Piece::L, Piece::S, Piece::Z, Piece::T];
+
+/// `[piece][from][to]`
+pub type RotationOffsets = [[[PieceCoords; 4]; 4]; 7];
+/// `[piece][from][to]`
+pub type KickTables = [[[&'static [[i8; 2]]; 4]; 4]; 7];
+/// kicks of one kind of turn, indexed by `from`
+type KickTable = [&'static [[i8; 2]]; 4];
+/// `[piece][from][to]`
+pub type TurnTables = [[[KickedOffsets; 4]; 4]; 7];
+
+/// longest kick table of any rotation system
+pub const MAX_KICKS: usize = 6;
+
+/// The kick a rotation used.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Kick {
+    /// where it is in the kick table
+    pub index: usize,
+    /// the rotation was a 180, whose kick table is ordered differently
+    pub half_turn: bool,
+}
+
+/// cell offsets of a turn with each kick applied, in the order they're tried
+#[derive(Clone, Copy, Debug)]
+pub struct KickedOffsets {
+    offsets: [PieceCoords; MAX_KICKS],
+    len: usize,
+}
+
+impl KickedOffsets {
+    pub fn as_slice(&self) -> &[PieceCoords] {
+        &self.offsets[..self.len]
+    }
+}
+
+const SRS_OFFSETS: RotationOffsets = rotation_offsets();
+
+const NO_KICK: &[[i8; 2]] = &[[0, 0]];
+const IN_PLACE: KickTable = [NO_KICK; 4];
+
+/// indexed by `from`. `to` is always `from + 1`
+const JLSTZ_CW: KickTable = [
+    &[[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]], // 0-1
+    &[[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],     // 1-2
+    &[[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],    // 2-3
+    &[[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],  // 3-0
+];
+/// indexed by `from`. `to` is always `from - 1`
+const JLSTZ_CCW: KickTable = [
+    &[[0, 0], [1, 0], [1, 1], [0, -2], [1, -2]],    // 0-3
+    &[[0, 0], [1, 0], [1, -1], [0, 2], [1, 2]],     // 1-0
+    &[[0, 0], [-1, 0], [-1, 1], [0, -2], [-1, -2]], // 2-1
+    &[[0, 0], [-1, 0], [-1, -1], [0, 2], [-1, 2]],  // 3-2
+];
+const I_CW: KickTable = [
+    &[[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]], // 0-1
+    &[[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 1-2
+    &[[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 2-3
+    &[[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 3-0
+];
+const I_CCW: KickTable = [
+    &[[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 0-3
+    &[[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 1-0
+    &[[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 2-1
+    &[[0, 0], [-2, 0], [1, 0], [-2, -1], [1, 2]], // 3-2
+];
+const BOTRIS_O_CW: KickTable = [
+    &[[1, -1], [2, -1], [1, -2], [0, -1], [0, 0]],    // 0-1
+    &[[-1, -1], [-2, -1], [-1, -2], [0, -1], [0, 0]], // 1-2
+    &[[-1, 1], [-2, 1], [-1, 2], [0, 1], [0, 0]],     // 2-3
+    &[[1, 1], [2, 1], [1, 2], [0, 1], [0, 0]],        // 3-0
+];
+const BOTRIS_O_CCW: KickTable = [
+    &[[-1, -1], [-2, -1], [-1, -2], [0, -1], [0, 0]], // 0-3
+    &[[-1, 1], [-2, 1], [-1, 2], [0, 1], [0, 0]],     // 1-0
+    &[[1, 1], [2, 1], [1, 2], [0, 1], [0, 0]],        // 2-1
+    &[[1, -1], [2, -1], [1, -2], [0, -1], [0, 0]],    // 3-2
+];
+const SRS_PLUS_I_CW: KickTable = [
+    &[[0, 0], [1, 0], [-2, 0], [-2, -1], [1, 2]], // 0-1
+    &[[0, 0], [-1, 0], [2, 0], [-1, 2], [2, -1]], // 1-2
+    &[[0, 0], [2, 0], [-1, 0], [2, 1], [-1, -2]], // 2-3
+    &[[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 3-0
+];
+const SRS_PLUS_I_CCW: KickTable = [
+    &[[0, 0], [-1, 0], [2, 0], [2, 1], [-1, -2]], // 0-3
+    &[[0, 0], [-1, 0], [2, 0], [-1, -2], [2, 1]], // 1-0
+    &[[0, 0], [-2, 0], [1, 0], [-2, 1], [1, -2]], // 2-1
+    &[[0, 0], [1, 0], [-2, 0], [1, -2], [-2, 1]], // 3-2
+];
+/// indexed by `from`. `to` is always `from + 2`
+const SRS_PLUS_180: KickTable = [
+    &[[0, 0], [0, 1], [1, 1], [-1, 1], [1, 0], [-1, 0]], // 0-2
+    &[[0, 0], [1, 0], [1, 2], [1, 1], [0, 2], [0, 1]],   // 1-3
+    &[[0, 0], [0, -1], [-1, -1], [1, -1], [-1, 0], [1, 0]], // 2-0
+    &[[0, 0], [-1, 0], [-1, 2], [-1, 1], [0, 2], [0, 1]], // 3-1
+];
+
+const SRS_KICKS: KickTables =
+    kick_tables([I_CW, IN_PLACE, I_CCW], [IN_PLACE; 3], [JLSTZ_CW, IN_PLACE, JLSTZ_CCW]);
+const BOTRIS_SRS_KICKS: KickTables = kick_tables(
+    [I_CW, IN_PLACE, I_CCW],
+    [BOTRIS_O_CW, IN_PLACE, BOTRIS_O_CCW],
+    [JLSTZ_CW, IN_PLACE, JLSTZ_CCW],
+);
+const SRS_PLUS_KICKS: KickTables = kick_tables(
+    [SRS_PLUS_I_CW, SRS_PLUS_180, SRS_PLUS_I_CCW],
+    [IN_PLACE; 3],
+    [JLSTZ_CW, SRS_PLUS_180, JLSTZ_CCW],
+);
+const NO_KICKS: KickTables = kick_tables([IN_PLACE; 3], [IN_PLACE; 3], [IN_PLACE; 3]);
+
+static SRS_TURNS: TurnTables = turn_tables(&SRS_OFFSETS, &SRS_KICKS);
+static BOTRIS_SRS_TURNS: TurnTables = turn_tables(&SRS_OFFSETS, &BOTRIS_SRS_KICKS);
+static SRS_PLUS_TURNS: TurnTables = turn_tables(&SRS_OFFSETS, &SRS_PLUS_KICKS);
+static NO_KICKS_TURNS: TurnTables = turn_tables(&SRS_OFFSETS, &NO_KICKS);
+
+impl RotationSystem for Srs {
+    fn kicks(&self, piece: Piece, from: u8, to: u8) -> &'static [[i8; 2]] {
+        SRS_KICKS[piece as usize][from as usize][to as usize]
+    }
+
+    fn turns(&self) -> &'static TurnTables {
+        &SRS_TURNS
+    }
+}
+
+impl RotationSystem for BotrisSrs {
+    fn kicks(&self, piece: Piece, from: u8, to: u8) -> &'static [[i8; 2]] {
+        BOTRIS_SRS_KICKS[piece as usize][from as usize][to as usize]
+    }
+
+    fn turns(&self) -> &'static TurnTables {
+        &BOTRIS_SRS_TURNS
+    }
+}
+
+impl RotationSystem for SrsPlus {
+    fn kicks(&self, piece: Piece, from: u8, to: u8) -> &'static [[i8; 2]] {
+        SRS_PLUS_KICKS[piece as usize][from as usize][to as usize]
+    }
+
+    fn turns(&self) -> &'static TurnTables {
+        &SRS_PLUS_TURNS
+    }
+}
+
+impl RotationSystem for NoKicks {
+    fn kicks(&self, piece: Piece, from: u8, to: u8) -> &'static [[i8; 2]] {
+        NO_KICKS[piece as usize][from as usize][to as usize]
+    }
+
+    fn turns(&self) -> &'static TurnTables {
+        &NO_KICKS_TURNS
+    }
+}
+
+/// Spreads `[cw, 180, ccw]` tables of the I, the O and every other piece over `[piece][from][to]`.
+/// Turning to the same rotation has no kicks.
+const fn kick_tables(i: [KickTable; 3], o: [KickTable; 3], jlstz: [KickTable; 3]) -> KickTables {
+    let mut tables: KickTables = [[[&[]; 4]; 4]; 7];
+    let mut piece = 0;
+    while piece < 7 {
+        let turns = match PIECES[piece] {
+            Piece::I => &i,
+            Piece::O => &o,
+            _ => &jlstz,
+        };
+        let mut from = 0;
+        while from < 4 {
+            // clockwise quarter turns
+            let mut turn = 1;
+            while turn < 4 {
+                tables[piece][from][(from + turn) % 4] = turns[turn - 1][from];
+                turn += 1;
+            }
+            from += 1;
+        }
+        piece += 1;
+    }
+    tables
+}
+
+const fn turn_tables(offsets: &RotationOffsets, kicks: &KickTables) -> TurnTables {
+    let empty = KickedOffsets { offsets: [[(0, 0); 4]; MAX_KICKS], len: 0 };
+    let mut tables = [[[empty; 4]; 4]; 7];
+    let mut piece = 0;
+    while piece < 7 {
+        let mut from = 0;
+        while from < 4 {
+            let mut to = 0;
+            while to < 4 {
+                let turn_kicks = kicks[piece][from][to];
+                assert!(turn_kicks.len() <= MAX_KICKS);
+                let turn = &mut tables[piece][from][to];
+                while turn.len < turn_kicks.len() {
+                    let [dx, dy] = turn_kicks[turn.len];
+                    let mut cell = 0;
+                    while cell < 4 {
+                        let (y, x) = offsets[piece][from][to][cell];
+                        turn.offsets[turn.len][cell] = (y + dy, x + dx);
+                        cell += 1;
+                    }
+                    turn.len += 1;
+                }
+                to += 1;
+            }
+            from += 1;
+        }
+        piece += 1;
+    }
+    tables
+}
+
+const fn rotation_offsets() -> RotationOffsets {
+    let mut offsets = [[[[(0, 0); 4]; 4]; 4]; 7];
+    let mut piece = 0;
+    while piece < 7 {
+        let shapes = srs_shapes(PIECES[piece]);
+        let mut from = 0;
+        while from < 4 {
+            let mut to = 0;
+            while to < 4 {
+                let mut cell = 0;
+                while cell < 4 {
+                    offsets[piece][from][to][cell] = (
+                        shapes[to][cell].0 - shapes[from][cell].0,
+                        shapes[to][cell].1 - shapes[from][cell].1,
+                    );
+                    cell += 1;
+                }
+                to += 1;
+            }
+            from += 1;
+        }
+        piece += 1;
+    }
+    offsets
+}
+
+/// (row, col) cells of each rotation state within a 4x4 grid. rotation 0 is the spawn orientation.
+pub const fn srs_shapes(piece: Piece) -> [PieceCoords; 4] {
+    match piece {
+        Piece::I => [
+            [(2, 0), (2, 1), (2, 2), (2, 3)],
+            [(0, 2), (1, 2), (2, 2), (3, 2)],
+            [(1, 0), (1, 1), (1, 2), (1, 3)],
+            [(0, 1), (1, 1), (2, 1), (3, 1)],
+        ],
+        Piece::J => [
+            [(2, 0), (2, 1), (2, 2), (3, 0)],
+            [(1, 1), (2, 1), (3, 1), (3, 2)],
+            [(2, 0), (2, 1), (2, 2), (1, 2)],
+            [(1, 0), (1, 1), (2, 1), (3, 1)],
+        ],
+        Piece::L => [
+            [(2, 0), (2, 1), (2, 2), (3, 2)],
+            [(1, 1), (1, 2), (2, 1), (3, 1)],
+            [(1, 0), (2, 0), (2, 1), (2, 2)],
+            [(1, 1), (2, 1), (3, 0), (3, 1)],
+        ],
+        Piece::O => [[(2, 1), (2, 2), (3, 1), (3, 2)]; 4],
+        Piece::S => [
+            [(2, 0), (2, 1), (3, 1), (3, 2)],
+            [(1, 2), (2, 1), (2, 2), (3, 1)],
+            [(1, 0), (1, 1), (2, 1), (2, 2)],
+            [(1, 1), (2, 0), (2, 1), (3, 0)],
+        ],
+        Piece::T => [
+            [(2, 0), (2, 1), (2, 2), (3, 1)],
+            [(1, 1), (2, 1), (3, 1), (2, 2)],
+            [(2, 0), (1, 1), (2, 1), (2, 2)],
+            [(1, 1), (2, 0), (2, 1), (3, 1)],
+        ],
+        Piece::Z => [
+            [(2, 1), (2, 2), (3, 0), (3, 1)],
+            [(1, 1), (2, 1), (2, 2), (3, 2)],
+            [(1, 1), (1, 2), (2, 0), (2, 1)],
+            [(1, 0), (2, 0), (2, 1), (3, 1)],
+        ],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{srs_shapes, NoKicks, RotationSystem, Srs, SrsPlus, PIECES};
+    use crate::{
+        botris::types::{Command::*, Piece},
+        tetris_core::{engine::BitBoard, piece::FallingPiece, ruleset::Ruleset},
+    };
+
+    #[test]
+    fn test_wall_kick() {
+        let board = BitBoard::from_strs(&["                    "]);
+        let srs = Ruleset { rotation_system: &Srs, ..Ruleset::GUIDELINE };
+        let no_kicks = Ruleset { rotation_system: &NoKicks, ..Ruleset::GUIDELINE };
+        // T pointing right against the left wall
+        let t =
+            board.try_commands(&FallingPiece::new(Piece::T), &[RotateCw, SonicLeft], &srs).unwrap();
+        let kicked = board.try_command(&t, RotateCw, &srs).unwrap();
+        assert_eq!(kicked.rotation, 2);
+        assert!(kicked.coords.iter().all(|&(_, x)| x >= 0));
+        assert_eq!(board.try_command(&t, RotateCw, &no_kicks), None);
+    }
+
+    #[test]
+    fn test_kick_tables() {
+        for piece in [Piece::I, Piece::J, Piece::L, Piece::O, Piece::S, Piece::T, Piece::Z] {
+            for from in 0..4 {
+                for turn in [1, 3] {
+                    let to = (from + turn) % 4;
+                    assert_eq!(Srs.kicks(piece, from, to)[0], [0, 0]);
+                    assert!(!SrsPlus.kicks(piece, from, to).is_empty());
+                }
+                assert_eq!(Srs.kicks(piece, from, (from + 2) % 4), [[0, 0]]);
+                assert!(!SrsPlus.kicks(piece, from, (from + 2) % 4).is_empty());
+                assert!(Srs.kicks(piece, from, from).is_empty());
+            }
+        }
+        // every kick ends up in the turn tables
+        let turn = SrsPlus.turns()[Piece::T as usize][0][2];
+        assert_eq!(turn.as_slice().len(), SrsPlus.kicks(Piece::T, 0, 2).len());
+        // the first cell of a T stays put when flipping, then the second kick moves it up
+        assert_eq!(turn.as_slice()[1][0], (1, 0));
+        // SRS+ I kicks are mirror images of each other
+        assert_eq!(SrsPlus.kicks(Piece::I, 0, 1)[1], [1, 0]);
+        assert_eq!(SrsPlus.kicks(Piece::I, 0, 3)[1], [-1, 0]);
+    }
+
+    #[test]
+    fn test_offsets_match_shapes() {
+        for piece in PIECES {
+            let shapes = srs_shapes(piece);
+            for from in 0..4 {
+                for to in 0..4 {
+                    let offsets = Srs.offsets(piece, from, to);
+                    for cell in 0..4 {
+                        let (y, x) = shapes[from as usize][cell];
+                        let (dy, dx) = offsets[cell];
+                        assert_eq!((y + dy, x + dx), shapes[to as usize][cell]);
+                    }
+                }
+            }
+        }
+    }
+}
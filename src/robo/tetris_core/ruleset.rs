@@ -0,0 +1,203 @@
+//! Attack tables and garbage rules of a versus game.
+//! The engine only ever reads these through a `Ruleset`, so the same search can be pointed at different games.
+
+use super::{
+    rotation::{BotrisSrs, RotationSystem, Srs, SrsPlus},
+    spin::{Spin, SpinRule},
+};
+use crate::botris::{
+    game_info::{B2B_ATTACK, BOARD_HEIGHT, COMBO_TABLE},
+    types::ClearName::*,
+};
+
+/// How clears that continue back-to-back are rewarded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum B2bBonus {
+    /// same bonus for every clear that continues b2b
+    Flat(u32),
+    /// `(chain, bonus)` pairs sorted by chain. the bonus of the last pair with `chain <= b2b_chain` applies.
+    Chaining(&'static [(u32, u32)]),
+}
+
+/// How fast the falling piece falls on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gravity {
+    /// the piece stays where it is until it's dropped
+    Zero,
+    /// 20G: the piece falls as far as it can on spawn and after every command
+    Instant,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ruleset {
+    /// attack for clearing 0..=4 lines without a spin
+    pub line_clear_attack: [u32; 5],
+    /// attack for clearing 0..=3 lines with a T spin
+    pub t_spin_attack: [u32; 4],
+    /// attack for clearing 0..=3 lines with a T spin mini
+    pub t_mini_attack: [u32; 4],
+    /// attack for clearing 0..=3 lines with a spin of any piece that isn't a T spin
+    pub all_spin_attack: [u32; 4],
+    /// attack for clearing 0..=3 lines with an all spin mini
+    pub all_mini_attack: [u32; 4],
+    pub spin_rule: SpinRule,
+    /// added on top of everything else when a clear empties the board
+    pub perfect_clear_bonus: u32,
+    /// indexed by combo (1 = first clear). the last entry is used for longer combos.
+    pub combo_table: &'static [u32],
+    pub b2b_bonus: B2bBonus,
+    /// attack offsets incoming garbage before anything is sent
+    pub garbage_cancel: bool,
+    /// garbage stays queued while the player keeps clearing lines
+    pub clear_delays_garbage: bool,
+    /// pieces spawn in the top visible row
+    pub visible_height: usize,
+    /// garbage can't push the stack higher than this
+    pub board_height: usize,
+    pub rotation_system: &'static dyn RotationSystem,
+    /// `Command::Rotate180` can be used
+    pub allow_180: bool,
+    pub gravity: Gravity,
+    /// how many times moving a piece that's touching the stack can restart its lock delay.
+    /// None if pieces never lock on their own.
+    pub lock_reset_limit: Option<u32>,
+}
+
+impl Ruleset {
+    /// Botris Battle. Reproduces `botris::game_info`.
+    pub const BOTRIS: Ruleset = Ruleset {
+        line_clear_attack: [0, Single.attack(), Double.attack(), Triple.attack(), Quad.attack()],
+        // there are no T spins or minis, every spin is an all spin
+        t_spin_attack: [0, ASS.attack(), ASD.attack(), AST.attack()],
+        t_mini_attack: [0, ASS.attack(), ASD.attack(), AST.attack()],
+        all_spin_attack: [0, ASS.attack(), ASD.attack(), AST.attack()],
+        all_mini_attack: [0, ASS.attack(), ASD.attack(), AST.attack()],
+        spin_rule: SpinRule::Immobile,
+        perfect_clear_bonus: PC.attack(),
+        combo_table: &COMBO_TABLE,
+        b2b_bonus: B2bBonus::Flat(B2B_ATTACK),
+        garbage_cancel: true,
+        clear_delays_garbage: true,
+        visible_height: 20,
+        board_height: BOARD_HEIGHT,
+        rotation_system: &BotrisSrs,
+        allow_180: false,
+        gravity: Gravity::Zero,
+        lock_reset_limit: None,
+    };
+
+    /// TETR.IO league. Its combo multiplier is approximated by a table.
+    pub const TETRIO: Ruleset = Ruleset {
+        line_clear_attack: [0, 0, 1, 2, 4],
+        t_spin_attack: [0, 2, 4, 6],
+        t_mini_attack: [0, 0, 1, 2],
+        // spins of other pieces only ever count as minis
+        all_spin_attack: [0, 0, 1, 2],
+        all_mini_attack: [0, 0, 1, 2],
+        spin_rule: SpinRule::AllMini,
+        perfect_clear_bonus: 10,
+        combo_table: &[0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+        b2b_bonus: B2bBonus::Chaining(&[(1, 1), (3, 2), (8, 3), (24, 4), (67, 5)]),
+        garbage_cancel: true,
+        clear_delays_garbage: true,
+        visible_height: 20,
+        board_height: 40,
+        rotation_system: &SrsPlus,
+        allow_180: true,
+        gravity: Gravity::Zero,
+        lock_reset_limit: Some(15),
+    };
+
+    /// Guideline versus, as in Puyo Puyo Tetris.
+    pub const GUIDELINE: Ruleset = Ruleset {
+        line_clear_attack: [0, 0, 1, 2, 4],
+        t_spin_attack: [0, 2, 4, 6],
+        t_mini_attack: [0, 0, 1, 2],
+        // only T pieces spin, anything else is a plain clear
+        all_spin_attack: [0, 0, 1, 2],
+        all_mini_attack: [0, 0, 1, 2],
+        spin_rule: SpinRule::TSpin,
+        perfect_clear_bonus: 10,
+        combo_table: &[0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+        b2b_bonus: B2bBonus::Flat(1),
+        garbage_cancel: true,
+        clear_delays_garbage: true,
+        visible_height: 20,
+        board_height: 40,
+        rotation_system: &Srs,
+        allow_180: false,
+        gravity: Gravity::Zero,
+        lock_reset_limit: Some(15),
+    };
+
+    /// Attack for the clear itself, ignoring combo, b2b and perfect clears.
+    /// None if no such clear exists in this ruleset.
+    pub fn clear_attack(&self, lines: usize, spin: Spin) -> Option<u32> {
+        let table: &[u32] = match spin {
+            Spin::None => &self.line_clear_attack,
+            Spin::TSpin => &self.t_spin_attack,
+            Spin::TSpinMini => &self.t_mini_attack,
+            Spin::AllSpin => &self.all_spin_attack,
+            Spin::AllMini => &self.all_mini_attack,
+        };
+        table.get(lines).copied()
+    }
+
+    pub fn combo_attack(&self, combo: u32) -> u32 {
+        let index = std::cmp::min(combo as usize, self.combo_table.len() - 1);
+        self.combo_table[index]
+    }
+
+    /// `b2b_chain` counts clears in a row that continued b2b (1 = first continuation)
+    pub fn b2b_attack(&self, b2b_chain: u32) -> u32 {
+        if b2b_chain == 0 {
+            return 0;
+        }
+        match self.b2b_bonus {
+            B2bBonus::Flat(bonus) => bonus,
+            B2bBonus::Chaining(levels) => levels
+                .iter()
+                .take_while(|&&(chain, _)| chain <= b2b_chain)
+                .last()
+                .map_or(0, |&(_, bonus)| bonus),
+        }
+    }
+}
+
+impl Default for Ruleset {
+    fn default() -> Self {
+        Ruleset::BOTRIS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Ruleset;
+    use crate::tetris_core::spin::Spin;
+
+    #[test]
+    fn test_b2b_chaining() {
+        let rules = Ruleset::TETRIO;
+        let bonuses: Vec<u32> = (0..10).map(|chain| rules.b2b_attack(chain)).collect();
+        assert_eq!(bonuses, [0, 1, 1, 2, 2, 2, 2, 2, 3, 3]);
+        assert_eq!(Ruleset::BOTRIS.b2b_attack(50), 1);
+    }
+
+    #[test]
+    fn test_spin_tables() {
+        let rules = Ruleset::TETRIO;
+        assert_eq!(rules.clear_attack(2, Spin::TSpin), Some(4));
+        assert_eq!(rules.clear_attack(2, Spin::AllSpin), Some(1));
+        assert_eq!(rules.clear_attack(3, Spin::TSpinMini), Some(2));
+        assert_eq!(rules.clear_attack(4, Spin::AllSpin), None);
+        assert_eq!(Ruleset::BOTRIS.clear_attack(2, Spin::AllSpin), Some(4));
+    }
+
+    #[test]
+    fn test_combo_table_repeats() {
+        let rules = Ruleset::BOTRIS;
+        assert_eq!(rules.combo_attack(1), 0);
+        assert_eq!(rules.combo_attack(9), 4);
+        assert_eq!(rules.combo_attack(100), 4);
+    }
+}
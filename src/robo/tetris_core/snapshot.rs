@@ -1,22 +1,26 @@
 use std::hash::Hash;
 
-use rand::{seq::SliceRandom, thread_rng};
+use rand::{seq::SliceRandom, Rng};
 
-use super::{engine::*, piece::*};
-use crate::botris::types::{GameState, GarbageLine, Piece};
+use super::{engine::*, garbage::GarbageHoles, piece::*};
+use crate::botris::types::{Block, Board, GameState, GarbageLine, Piece};
 
 #[derive(Debug, Clone, Eq)]
 pub struct GameSnapshot {
     pub matrix: BitBoard,
     pub falling_piece: FallingPiece,
+    /// the pieces that can be seen coming
     pub queue: Vec<Piece>,
+    /// what's left of the 7-bag the last piece of `queue` came from, in no particular order.
+    /// these come right after `queue`, then whole bags.
+    pub bag: Vec<Piece>,
     pub held: Piece,
     pub can_hold: bool,
     pub combo: u32,
     pub b2b: bool,
     pub incoming_garbage: [u32; 8],
-    /// number of lines in the matrix that is treated is unclearable
-    pub permanent_garbage: usize,
+    /// what search assumes about the holes of incoming garbage
+    pub garbage_holes: GarbageHoles,
 }
 
 impl PartialEq for GameSnapshot {
@@ -44,15 +48,6 @@ impl Hash for GameSnapshot {
 
 impl GameSnapshot {
     pub fn from_state(game_state: &GameState) -> Self {
-        let mut queue = game_state.queue.clone();
-        queue.extend(game_state.bag.clone());
-        for _ in 0..5 {
-            let mut random_bag =
-                [Piece::I, Piece::J, Piece::L, Piece::O, Piece::S, Piece::T, Piece::Z];
-            random_bag.shuffle(&mut thread_rng());
-            queue.extend(random_bag);
-        }
-
         let mut incoming = [0; 8];
         for GarbageLine { delay } in &game_state.garbage_queued {
             // assume we can play above 2pps
@@ -63,18 +58,44 @@ impl GameSnapshot {
 
         GameSnapshot {
             matrix: to_board(&game_state.board),
-            queue,
+            queue: game_state.queue.clone(),
+            bag: game_state.bag.clone(),
             held: game_state.held.expect("no held piece in Frame"),
             falling_piece: FallingPiece::new(game_state.current.piece),
             can_hold: game_state.can_hold,
             combo: game_state.combo,
             b2b: game_state.b2b,
             incoming_garbage: incoming,
-            permanent_garbage: 0,
+            garbage_holes: guess_garbage_holes(&game_state.board),
+        }
+    }
+
+    /// `queue`, then one way the pieces after it could come: the rest of `bag` and whole bags after that,
+    /// shuffled by `rng`. At least `len` pieces long.
+    pub fn sample_queue(&self, rng: &mut impl Rng, len: usize) -> Vec<Piece> {
+        let mut queue = self.queue.clone();
+        let mut rest_of_bag = self.bag.clone();
+        rest_of_bag.shuffle(rng);
+        queue.extend(rest_of_bag);
+        while queue.len() < len {
+            let mut bag = [Piece::I, Piece::J, Piece::L, Piece::O, Piece::S, Piece::T, Piece::Z];
+            bag.shuffle(rng);
+            queue.extend(bag);
         }
+        queue
     }
 }
 
+/// assumes incoming garbage lines up with the hole of the highest garbage line on the board
+fn guess_garbage_holes(board: &Board) -> GarbageHoles {
+    board
+        .iter()
+        .rev()
+        .find(|row| row.iter().any(|cell| matches!(cell, Some(Block::G))))
+        .and_then(|row| row.iter().position(|cell| cell.is_none()))
+        .map_or(GarbageHoles::default(), |hole| GarbageHoles::Clean(hole as u8))
+}
+
 //             Hold => {
 //                 if !self.can_hold || self.held == self.falling_piece.piece {
 //                     return None;
@@ -134,12 +155,13 @@ impl Default for GameSnapshot {
             matrix: EMPTY_BOARD,
             falling_piece: FallingPiece::new(Piece::I),
             queue: vec![],
+            bag: vec![],
             held: Piece::O,
             can_hold: true,
             combo: 0,
             b2b: false,
             incoming_garbage: [0; 8],
-            permanent_garbage: 0,
+            garbage_holes: GarbageHoles::default(),
         }
     }
 }
@@ -166,6 +188,36 @@ impl Default for GameSnapshot {
 //     }
 // }
 
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::GameSnapshot;
+    use crate::botris::types::Piece;
+
+    #[test]
+    fn test_sample_queue() {
+        let snapshot = GameSnapshot {
+            queue: vec![Piece::T, Piece::I],
+            bag: vec![Piece::S, Piece::Z, Piece::O],
+            ..Default::default()
+        };
+        let sample = snapshot.sample_queue(&mut StdRng::seed_from_u64(0), 12);
+        assert_eq!(sample.len(), 12);
+        assert_eq!(sample[..2], [Piece::T, Piece::I]);
+        let mut rest_of_bag = sample[2..5].to_vec();
+        rest_of_bag.sort_unstable();
+        assert_eq!(rest_of_bag, [Piece::O, Piece::S, Piece::Z]);
+        let mut next_bag = sample[5..].to_vec();
+        next_bag.sort_unstable();
+        assert_eq!(
+            next_bag,
+            [Piece::I, Piece::O, Piece::J, Piece::L, Piece::S, Piece::Z, Piece::T]
+        );
+        assert_eq!(snapshot.sample_queue(&mut StdRng::seed_from_u64(0), 12), sample);
+    }
+}
+
 // #[cfg(test)]
 // mod test {
 //     use super::SomeGameState;
@@ -0,0 +1,212 @@
+//! Which placements are spins. Rulesets disagree on which pieces can spin and what makes a spin a mini,
+//! so the rule lives in `Ruleset::spin_rule`.
+
+use super::{engine::BitBoard, piece::FallingPiece, rotation::Kick, ruleset::Ruleset};
+use crate::botris::types::Piece;
+
+/// What a locked piece scores as, on top of its line clear.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum Spin {
+    #[default]
+    None,
+    /// a T spin with only one front corner filled
+    TSpinMini,
+    /// a T spin with both front corners filled, or kicked in by the last SRS kick
+    TSpin,
+    /// a piece rotated into a spot it can't move out of that isn't a T spin, scored like a mini
+    AllMini,
+    /// any piece, T included, that can't move up, left or right
+    AllSpin,
+}
+
+impl Spin {
+    pub fn is_mini(self) -> bool {
+        matches!(self, Spin::TSpinMini | Spin::AllMini)
+    }
+}
+
+/// Which placements a ruleset counts as spins.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpinRule {
+    /// Botris: every immobile piece is an `AllSpin`, however it got there
+    Immobile,
+    /// TETR.IO: T spins by the 3-corner rule, anything else rotated into an immobile spot is an `AllMini`
+    AllMini,
+    /// guideline: only T spins, by the 3-corner rule
+    TSpin,
+}
+
+/// the quarter turn kick that upgrades a T spin mini to a T spin (the last kick of SRS, as in a TST).
+/// the same place in a 180 kick table is just another kick.
+const TST_KICK: usize = 4;
+
+/// `falling_piece` can't move up, left or right
+pub fn immobile<const W: usize>(board: &BitBoard<W>, falling_piece: &FallingPiece) -> bool {
+    [(1, 0), (0, 1), (0, -1)].iter().all(|&(dy, dx)| {
+        let mut nudged = *falling_piece;
+        nudged.shift(dy, dx);
+        board.collides(&nudged)
+    })
+}
+
+/// The spin `falling_piece` scores if it locks where it is.
+/// `last_kick` is the kick used if the last thing the piece did was rotate.
+pub fn classify<const W: usize>(
+    board: &BitBoard<W>,
+    falling_piece: &FallingPiece,
+    last_kick: Option<Kick>,
+    rules: &Ruleset,
+) -> Spin {
+    classify_with(board, falling_piece, last_kick, || immobile(board, falling_piece), rules)
+}
+
+/// `classify`, for callers that can tell whether the piece is `immobile` faster than trying to move it.
+/// `immobile` is only called if the spin rule needs it.
+pub fn classify_with<const W: usize>(
+    board: &BitBoard<W>,
+    falling_piece: &FallingPiece,
+    last_kick: Option<Kick>,
+    immobile: impl Fn() -> bool,
+    rules: &Ruleset,
+) -> Spin {
+    match (rules.spin_rule, falling_piece.piece, last_kick) {
+        (SpinRule::Immobile, ..) => match immobile() {
+            true => Spin::AllSpin,
+            false => Spin::None,
+        },
+        (_, _, None) => Spin::None,
+        (rule, piece, Some(kick)) => {
+            let t_spin = match piece {
+                Piece::T => t_spin(board, falling_piece, kick),
+                _ => Spin::None,
+            };
+            match t_spin {
+                Spin::None if rule == SpinRule::AllMini && immobile() => Spin::AllMini,
+                spin => spin,
+            }
+        }
+    }
+}
+
+/// the 3-corner rule, with the front corners deciding minis
+fn t_spin<const W: usize>(board: &BitBoard<W>, falling_piece: &FallingPiece, kick: Kick) -> Spin {
+    let coords = &falling_piece.coords;
+    // the only cell next to all the others
+    let &(y, x) = coords
+        .iter()
+        .find(|&&(y, x)| {
+            coords
+                .iter()
+                .filter(|&&(other_y, other_x)| (y - other_y).abs() + (x - other_x).abs() == 1)
+                .count()
+                == 3
+        })
+        .unwrap();
+    let (dy, dx) = [(1, 0), (0, 1), (-1, 0), (0, -1)][falling_piece.rotation as usize];
+    // walls and the floor count as filled
+    let filled = |corner_y: i8, corner_x: i8| {
+        corner_y < 0
+            || corner_x < 0
+            || corner_x >= W as i8
+            || board.cols[corner_x as usize] >> corner_y & 1 == 1
+    };
+    let front = [filled(y + dy + dx, x + dx + dy), filled(y + dy - dx, x + dx - dy)];
+    let back = [filled(y - dy + dx, x - dx + dy), filled(y - dy - dx, x - dx - dy)];
+    let front_filled = front.iter().filter(|&&corner| corner).count();
+    let back_filled = back.iter().filter(|&&corner| corner).count();
+    let tst_kick = kick.index == TST_KICK && !kick.half_turn;
+    match (front_filled + back_filled >= 3, front_filled == 2 || tst_kick) {
+        (false, _) => Spin::None,
+        (true, true) => Spin::TSpin,
+        (true, false) => Spin::TSpinMini,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{classify, Spin};
+    use crate::{
+        botris::types::{Command, Piece},
+        tetris_core::{engine::BitBoard, piece::FallingPiece, rotation::Kick, ruleset::Ruleset},
+    };
+
+    /// the last move was a quarter turn with kick `index`
+    fn quarter(index: usize) -> Option<Kick> {
+        Some(Kick { index, half_turn: false })
+    }
+
+    #[test]
+    fn test_t_spin_corners() {
+        let board = BitBoard::from_strs(&[
+            "      []            ",
+            "[][][]      [][][][]",
+            "[][][][]  [][][][][]",
+        ]);
+        let tsd =
+            FallingPiece { piece: Piece::T, rotation: 2, coords: [(1, 3), (0, 4), (1, 4), (1, 5)] };
+        for rules in [Ruleset::TETRIO, Ruleset::GUIDELINE] {
+            assert_eq!(classify(&board, &tsd, quarter(0), &rules), Spin::TSpin);
+            assert_eq!(classify(&board, &tsd, None, &rules), Spin::None);
+        }
+        assert_eq!(classify(&board, &tsd, None, &Ruleset::BOTRIS), Spin::AllSpin);
+        let (_, _, tetrio) = board
+            .lock_piece(&tsd.coords, Spin::TSpin, Default::default(), &Ruleset::TETRIO, None)
+            .unwrap();
+        assert_eq!(tetrio.attack, 4);
+
+        // the floor fills both back corners, only one front corner is filled
+        let board = BitBoard::from_strs(&["[]                  "]);
+        let mini =
+            FallingPiece { piece: Piece::T, rotation: 0, coords: [(0, 0), (0, 1), (0, 2), (1, 1)] };
+        let board = BitBoard { cols: board.cols.map(|col| col << 1) };
+        assert_eq!(classify(&board, &mini, quarter(0), &Ruleset::TETRIO), Spin::TSpinMini);
+        assert_eq!(classify(&board, &mini, quarter(4), &Ruleset::TETRIO), Spin::TSpin);
+    }
+
+    #[test]
+    fn test_half_turn_kick() {
+        let rules = Ruleset::TETRIO;
+        let board = BitBoard::from_strs(&[
+            "            []      ",
+            "                    ",
+            "            []  []  ",
+            "                []  ",
+            "                    ",
+            "                    ",
+        ]);
+        let t =
+            FallingPiece { piece: Piece::T, rotation: 3, coords: [(1, 7), (2, 6), (2, 7), (3, 7)] };
+        // the fifth kick of a 180, where a quarter turn's TST kick would be
+        let (turned, kick) = board.try_command_with_kick(&t, Command::Rotate180, &rules).unwrap();
+        assert_eq!(kick, Some(Kick { index: 4, half_turn: true }));
+        // one front corner filled, so it stays a mini
+        assert_eq!(classify(&board, &turned, kick, &rules), Spin::TSpinMini);
+        assert_eq!(classify(&board, &turned, quarter(4), &rules), Spin::TSpin);
+    }
+
+    #[test]
+    fn test_all_mini() {
+        let board = BitBoard::from_strs(&[
+            "[][][][]    [][][][]",
+            "[][][]    [][][][][]",
+            "[][][][][][][][][]  ",
+        ]);
+        let s =
+            FallingPiece { piece: Piece::S, rotation: 0, coords: [(1, 3), (1, 4), (2, 4), (2, 5)] };
+        assert_eq!(classify(&board, &s, quarter(2), &Ruleset::TETRIO), Spin::AllMini);
+        assert_eq!(classify(&board, &s, quarter(2), &Ruleset::GUIDELINE), Spin::None);
+        assert_eq!(classify(&board, &s, None, &Ruleset::TETRIO), Spin::None);
+        assert_eq!(classify(&board, &s, None, &Ruleset::BOTRIS), Spin::AllSpin);
+        let (_, _, mini) = board
+            .lock_piece(&s.coords, Spin::AllMini, Default::default(), &Ruleset::TETRIO, None)
+            .unwrap();
+        assert_eq!(mini.attack, Ruleset::TETRIO.all_mini_attack[2]);
+        assert!(mini.b2b);
+        // an S spin double doesn't score like a T spin double
+        let (_, _, spin) = board
+            .lock_piece(&s.coords, Spin::AllSpin, Default::default(), &Ruleset::TETRIO, None)
+            .unwrap();
+        assert_eq!(spin.attack, 1);
+        assert_ne!(spin.attack, Ruleset::TETRIO.t_spin_attack[2]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Checks that an action from `move_gen_with_action` does what it claims,
+//! by replaying it from spawn the way the server would.
+
+use std::{error::Error, fmt::Display};
+
+use crate::{
+    botris::types::Command,
+    movegen::Placement,
+    tetris_core::{piece::FallingPiece, ruleset::Ruleset, snapshot::GameSnapshot},
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ActionError {
+    /// the piece that would be played can't spawn
+    BlockedSpawn,
+    /// the server would skip this command instead of moving the piece
+    Blocked { index: usize, command: Command },
+    /// only the first command can hold, and only if holding is allowed
+    BadHold { index: usize },
+    /// the piece would lock before this command, out of lock resets
+    LockedOut { index: usize },
+    /// the piece locks somewhere other than where it was meant to
+    WrongPlacement { expected: Placement, landed: Placement },
+}
+
+impl Display for ActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionError::BlockedSpawn => write!(f, "the piece can't spawn"),
+            ActionError::Blocked { index, command } => {
+                write!(f, "command {index} ({command}) can't be performed")
+            }
+            ActionError::BadHold { index } => write!(f, "command {index} holds when it can't"),
+            ActionError::LockedOut { index } => {
+                write!(f, "the piece locks before command {index}")
+            }
+            ActionError::WrongPlacement { expected, landed } => {
+                write!(f, "expected {expected:?}, landed {landed:?}")
+            }
+        }
+    }
+}
+
+impl Error for ActionError {}
+
+/// Where `action` locks the falling piece of `snapshot`. Stops at `HardDrop`, or hard drops after
+/// the last command, like the server. Unlike the server, commands that do nothing are errors.
+pub fn replay_action(
+    snapshot: &GameSnapshot,
+    action: &[Command],
+    rules: &Ruleset,
+) -> Result<Placement, ActionError> {
+    let board = &snapshot.matrix;
+    let (piece, commands) = match action.first() {
+        Some(Command::Hold) if snapshot.can_hold => (snapshot.held, &action[1..]),
+        Some(Command::Hold) => return Err(ActionError::BadHold { index: 0 }),
+        _ => (snapshot.falling_piece.piece, action),
+    };
+    let spawn = FallingPiece::spawn(piece, board.cols.len(), rules.visible_height);
+    if board.collides(&spawn) {
+        return Err(ActionError::BlockedSpawn);
+    }
+
+    let mut falling_piece = board.apply_gravity(&spawn, rules);
+    let mut last_kick = None;
+    let mut lock_resets = 0;
+    let skipped = action.len() - commands.len();
+    for (index, &command) in commands.iter().enumerate() {
+        let index = index + skipped;
+        if command != Command::HardDrop && board.force_sonic_drop(&falling_piece) == falling_piece {
+            lock_resets += 1;
+            if rules.lock_reset_limit.is_some_and(|limit| lock_resets > limit) {
+                return Err(ActionError::LockedOut { index });
+            }
+        }
+        (falling_piece, last_kick) = match command {
+            Command::HardDrop => break,
+            Command::Hold => return Err(ActionError::BadHold { index }),
+            _ => board
+                .try_command_with_kick(&falling_piece, command, rules)
+                .ok_or(ActionError::Blocked { index, command })?,
+        };
+    }
+    let dropped = board.force_sonic_drop(&falling_piece);
+    Ok(Placement::new(board, &dropped, last_kick.filter(|_| dropped == falling_piece), rules))
+}
+
+/// `replay_action`, checked against where the action should have put the piece
+pub fn validate_action(
+    snapshot: &GameSnapshot,
+    action: &[Command],
+    expected: &Placement,
+    rules: &Ruleset,
+) -> Result<(), ActionError> {
+    let landed = replay_action(snapshot, action, rules)?;
+    match landed == *expected {
+        true => Ok(()),
+        false => Err(ActionError::WrongPlacement { expected: *expected, landed }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::{replay_action, validate_action, ActionError};
+    use crate::{
+        botris::types::{Command, Piece},
+        movegen::{move_gen_snapshot, Move, Placement},
+        tetris_core::{
+            engine::{BitBoard, EMPTY_BOARD},
+            game::{Event, Game},
+            piece::FallingPiece,
+            rotation::PIECES,
+            ruleset::{Gravity, Ruleset},
+            snapshot::GameSnapshot,
+        },
+    };
+
+    const RULESETS: [Ruleset; 4] = [
+        Ruleset::BOTRIS,
+        Ruleset::TETRIO,
+        Ruleset::GUIDELINE,
+        Ruleset { gravity: Gravity::Instant, ..Ruleset::TETRIO },
+    ];
+
+    /// bottom row first. every row has a block and a gap, so lines never clear on their own.
+    fn board_from(rows: &[u16]) -> BitBoard {
+        let mut board = EMPTY_BOARD;
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..10 {
+                board.set(y, x, row >> x & 1 == 1);
+            }
+        }
+        board
+    }
+
+    /// plays `action` in a `Game` set up like `snapshot` and returns where the piece locked
+    fn play(snapshot: &GameSnapshot, action: &[Command], rules: Ruleset) -> Placement {
+        let mut game = Game::new(0, rules);
+        game.board = snapshot.matrix;
+        game.falling_piece = game.board.apply_gravity(
+            &FallingPiece::spawn(snapshot.falling_piece.piece, 10, rules.visible_height),
+            &rules,
+        );
+        game.held = Some(snapshot.held);
+        game.can_hold = snapshot.can_hold;
+        let events = game.apply(action);
+        let Some(&Event::PieceLocked { mut coords, spin, .. }) =
+            events.iter().find(|event| matches!(event, Event::PieceLocked { .. }))
+        else {
+            panic!("{action:?} didn't lock a piece: {events:?}");
+        };
+        coords.sort_unstable();
+        Placement { piece_location: coords, spin }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_actions_replay(
+            rows in prop::collection::vec(1u16..1023, 0..12),
+            rules in 0..RULESETS.len(),
+        ) {
+            let rules = RULESETS[rules];
+            let board = board_from(&rows);
+            for (piece, held) in PIECES.into_iter().zip(PIECES.into_iter().cycle().skip(3)) {
+                let snapshot = GameSnapshot {
+                    matrix: board,
+                    falling_piece: FallingPiece::new(piece),
+                    held,
+                    can_hold: true,
+                    ..Default::default()
+                };
+                for Move { placement, action, .. } in move_gen_snapshot(&snapshot, &rules) {
+                    let valid = validate_action(&snapshot, &action, &placement, &rules);
+                    prop_assert_eq!(valid, Ok(()));
+                    prop_assert_eq!(play(&snapshot, &action, rules), placement);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_action_errors() {
+        let rules = Ruleset::BOTRIS;
+        let snapshot = GameSnapshot {
+            falling_piece: FallingPiece::new(Piece::T),
+            held: Piece::I,
+            can_hold: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            replay_action(&snapshot, &[Command::Hold], &rules),
+            Err(ActionError::BadHold { index: 0 })
+        );
+        let blocked = [Command::SonicLeft, Command::MoveLeft];
+        assert_eq!(
+            replay_action(&snapshot, &blocked, &rules),
+            Err(ActionError::Blocked { index: 1, command: Command::MoveLeft })
+        );
+        let placement = replay_action(&snapshot, &[Command::SonicLeft], &rules).unwrap();
+        assert_eq!(placement.piece_location, [(0, 0), (0, 1), (0, 2), (1, 1)]);
+        assert!(matches!(
+            validate_action(&snapshot, &[Command::SonicRight], &placement, &rules),
+            Err(ActionError::WrongPlacement { .. })
+        ));
+    }
+}
@@ -0,0 +1,189 @@
+//! Bot vs bot matches played entirely in process, for comparing evaluators and search strategies
+//! without a live room.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    botris::types::Command,
+    search::Search,
+    tetris_core::{
+        game::{Event, Game},
+        ruleset::Ruleset,
+    },
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct MatchConfig {
+    pub games: u32,
+    /// game `i` is played with seed `seed + i`. both players get the same pieces.
+    pub seed: u64,
+    pub rules: Ruleset,
+    /// placements before attack reaches the opponent's board
+    pub garbage_delay: usize,
+    /// a game is a draw once both players placed this many pieces
+    pub max_pieces: u32,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            games: 10,
+            seed: 0,
+            rules: Ruleset::BOTRIS,
+            garbage_delay: 1,
+            max_pieces: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlayerStats {
+    pub wins: u32,
+    pub pieces: u32,
+    pub attack: u32,
+    /// time spent in `suggest_action`
+    pub think_time: Duration,
+}
+
+impl PlayerStats {
+    /// pieces per second of thinking, not of game time, so not comparable to a room's pps.
+    /// 0 if the bot never thought.
+    pub fn think_pps(&self) -> f32 {
+        per_second(self.pieces as f32, self.think_time)
+    }
+    /// attack per minute of thinking. 0 if the bot never thought.
+    pub fn think_apm(&self) -> f32 {
+        per_second(self.attack as f32 * 60.0, self.think_time)
+    }
+    /// attack per piece. 0 if no pieces were placed.
+    pub fn app(&self) -> f32 {
+        match self.pieces {
+            0 => 0.0,
+            pieces => self.attack as f32 / pieces as f32,
+        }
+    }
+}
+
+fn per_second(count: f32, time: Duration) -> f32 {
+    match time.is_zero() {
+        true => 0.0,
+        false => count / time.as_secs_f32(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchStats {
+    pub games: u32,
+    pub draws: u32,
+    pub players: [PlayerStats; 2],
+}
+
+impl MatchStats {
+    pub fn win_rate(&self, player: usize) -> f32 {
+        self.players[player].wins as f32 / self.games as f32
+    }
+    /// in pieces placed by each player
+    pub fn average_game_length(&self) -> f32 {
+        (self.players[0].pieces + self.players[1].pieces) as f32 / (2 * self.games) as f32
+    }
+
+    pub fn print(&self) {
+        println!("{} games, {} draws", self.games, self.draws);
+        for (i, player) in self.players.iter().enumerate() {
+            println!(
+                "P{}: {:5.1}% wins {:6.2}pps {:7.1}apm (per think time) {:5.3}app",
+                i + 1,
+                100.0 * self.win_rate(i),
+                player.think_pps(),
+                player.think_apm(),
+                player.app(),
+            );
+        }
+        println!("average game length: {:.1} pieces", self.average_game_length());
+    }
+}
+
+pub fn play_match(
+    bot1: &mut impl Search,
+    bot2: &mut impl Search,
+    config: &MatchConfig,
+) -> MatchStats {
+    let mut stats = MatchStats::default();
+    for i in 0..config.games {
+        let seed = config.seed.wrapping_add(i as u64);
+        let mut games = [Game::new(seed, config.rules), Game::new(seed, config.rules)];
+
+        while !games.iter().any(|game| game.dead)
+            && games.iter().any(|game| game.pieces_placed < config.max_pieces)
+        {
+            for player in 0..2 {
+                let start_time = Instant::now();
+                let commands = match games[player].snapshot() {
+                    None => vec![Command::Hold],
+                    Some(snapshot) if player == 0 => bot1.suggest_action(&snapshot, &config.rules),
+                    Some(snapshot) => bot2.suggest_action(&snapshot, &config.rules),
+                };
+                stats.players[player].think_time += start_time.elapsed();
+
+                for event in games[player].apply(&commands) {
+                    if let Event::PieceLocked { clear, .. } = event {
+                        stats.players[player].attack += clear.attack_sent;
+                        if clear.attack_sent > 0 {
+                            games[1 - player]
+                                .receive_garbage(clear.attack_sent, config.garbage_delay);
+                        }
+                    }
+                }
+                if games[player].dead {
+                    break;
+                }
+            }
+        }
+
+        match (games[0].dead, games[1].dead) {
+            (false, true) => stats.players[0].wins += 1,
+            (true, false) => stats.players[1].wins += 1,
+            _ => stats.draws += 1,
+        }
+        for (player, game) in stats.players.iter_mut().zip(&games) {
+            player.pieces += game.pieces_placed;
+        }
+        stats.games += 1;
+    }
+    stats
+}
+
+#[cfg(test)]
+mod test {
+    use super::{play_match, MatchConfig, PlayerStats};
+    use crate::{akirobo::Akirobo, evaluation::NoEval, mcts::Mcts};
+
+    #[test]
+    fn test_play_match() {
+        let mut bot1 = Akirobo::with_evaluator(NoEval {});
+        let mut bot2 = Akirobo::with_evaluator(NoEval {});
+        bot1.verbose = false;
+        bot2.verbose = false;
+        let config = MatchConfig { games: 2, max_pieces: 30, ..Default::default() };
+        let stats = play_match(&mut bot1, &mut bot2, &config);
+        stats.print();
+        assert_eq!(stats.games, 2);
+        assert_eq!(stats.players[0].wins + stats.players[1].wins + stats.draws, 2);
+        assert!(stats.average_game_length() <= 30.0);
+
+        // nothing played, nothing to divide by
+        let idle = PlayerStats::default();
+        assert_eq!((idle.think_pps(), idle.think_apm(), idle.app()), (0.0, 0.0, 0.0));
+    }
+    #[test]
+    fn test_beam_versus_mcts() {
+        let mut beam = Akirobo::with_evaluator(NoEval {});
+        beam.verbose = false;
+        let mut mcts = Mcts::with_evaluator(NoEval {});
+        mcts.iterations = 50;
+        let config = MatchConfig { games: 1, max_pieces: 20, ..Default::default() };
+        let stats = play_match(&mut beam, &mut mcts, &config);
+        assert_eq!(stats.games, 1);
+        assert!(stats.players[1].pieces > 0);
+    }
+}
@@ -0,0 +1,38 @@
+use std::env::args;
+
+use owo_colors::OwoColorize;
+use robo::{
+    akirobo::Akirobo,
+    mcts::Mcts,
+    versus::{play_match, MatchConfig},
+};
+
+/// usage: versus [games] [seed] [beam|mcts]
+/// the last argument picks the strategy of the second player, beam search by default
+fn main() {
+    println!("{}", "Akirobo versus".blue().bold().on_white());
+
+    let mut args = args().skip(1);
+    let mut config = MatchConfig::default();
+    if let Some(games) = args.next() {
+        config.games = games.parse().expect("games should be a number");
+    }
+    if let Some(seed) = args.next() {
+        config.seed = seed.parse().expect("seed should be a number");
+    }
+
+    // swap in the evaluators to compare here
+    let mut bot1 = Akirobo::new();
+    bot1.verbose = false;
+
+    let stats = match args.next().as_deref() {
+        Some("mcts") => play_match(&mut bot1, &mut Mcts::new(), &config),
+        Some("beam") | None => {
+            let mut bot2 = Akirobo::new();
+            bot2.verbose = false;
+            play_match(&mut bot1, &mut bot2, &config)
+        }
+        Some(other) => panic!("unknown strategy {other}, expected beam or mcts"),
+    };
+    stats.print();
+}